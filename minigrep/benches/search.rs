@@ -0,0 +1,141 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use minigrep::{search, search_case_insensitive, Boundary, Match, Pattern, Searcher};
+
+/// Deterministic pseudo-random numbers, so every run searches the same
+/// text.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.next() % items.len()]
+    }
+}
+
+/// About 4 MB of application log: mostly INFO lines, a few percent errors.
+fn server_log() -> String {
+    let levels = ["INFO", "INFO", "INFO", "INFO", "INFO", "DEBUG", "DEBUG", "WARN", "ERROR"];
+    let paths = ["/api/users", "/api/orders", "/health", "/static/app.js", "/login", "/api/search?q=rust"];
+    let users = ["alice", "bob", "carol", "dave", "erin", "mallory"];
+    let messages = [
+        "request completed",
+        "cache miss, fetching from upstream",
+        "connection reset by peer",
+        "slow query detected",
+        "user session refreshed",
+        "retrying after timeout",
+    ];
+
+    let mut rng = Rng(42);
+    let mut log = String::new();
+
+    for second in 0..40_000 {
+        log.push_str(&format!(
+            "2024-05-{:02}T{:02}:{:02}:{:02}Z {:5} [{}] {} user={} status={} latency_ms={}\n",
+            1 + second / 86_400,
+            second / 3_600 % 24,
+            second / 60 % 60,
+            second % 60,
+            rng.pick(&levels),
+            rng.pick(&paths),
+            rng.pick(&messages),
+            rng.pick(&users),
+            [200, 200, 200, 201, 304, 404, 500][rng.next() % 7],
+            rng.next() % 2_000,
+        ));
+    }
+
+    log
+}
+
+/// About 2 MB of prose, with capitalized sentences and some non-ASCII
+/// words for case folding to deal with.
+fn prose() -> String {
+    let words = [
+        "the", "of", "and", "to", "in", "a", "is", "that", "for", "it", "as", "was", "with", "be", "by", "on", "not",
+        "he", "this", "are", "or", "his", "from", "at", "which", "but", "have", "an", "had", "they", "you", "were",
+        "their", "one", "all", "we", "can", "her", "has", "there", "been", "if", "more", "when", "will", "would",
+        "who", "so", "no", "river", "mountain", "letter", "journey", "morning", "garden", "café", "naïve", "Straße",
+        "façade", "übermensch", "sherlock", "holmes", "watson", "baker", "street",
+    ];
+
+    let mut rng = Rng(7);
+    let mut text = String::new();
+
+    while text.len() < 2_000_000 {
+        let mut sentence: Vec<&str> = (0..5 + rng.next() % 15).map(|_| rng.pick(&words)).collect();
+        let mut first = sentence[0].chars();
+        let capitalized: String = first.next().into_iter().flat_map(char::to_uppercase).chain(first).collect();
+        sentence[0] = &capitalized;
+
+        text.push_str(&sentence.join(" "));
+        text.push_str(if rng.next().is_multiple_of(4) { ".\n" } else { ". " });
+    }
+
+    text
+}
+
+fn corpora() -> Vec<(&'static str, String, &'static str)> {
+    vec![("log", server_log(), "ERROR"), ("prose", prose(), "Holmes")]
+}
+
+/// The free functions: `search` and `search_case_insensitive`.
+fn free_functions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+
+    for (name, contents, query) in corpora() {
+        group.throughput(Throughput::Bytes(contents.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("case_sensitive", name), &contents, |b, contents| {
+            b.iter(|| search(black_box(query), black_box(contents)).len())
+        });
+        group.bench_with_input(BenchmarkId::new("case_insensitive", name), &contents, |b, contents| {
+            b.iter(|| search_case_insensitive(black_box(query), black_box(contents)).len())
+        });
+    }
+
+    group.finish();
+}
+
+/// The path the command line takes: a compiled [`Pattern`] run by a
+/// [`Searcher`] over a reader.
+fn searcher(c: &mut Criterion) {
+    let mut group = c.benchmark_group("searcher");
+
+    for (name, contents, query) in corpora() {
+        group.throughput(Throughput::Bytes(contents.len() as u64));
+
+        let query = [query.to_string()];
+        let patterns = [
+            ("literal", Pattern::literal(&query, true, Boundary::Substring).unwrap()),
+            ("literal_ignore_case", Pattern::literal(&query, false, Boundary::Substring).unwrap()),
+            ("regex_ignore_case", Pattern::regex(&query, false, Boundary::Substring).unwrap()),
+        ];
+
+        for (kind, pattern) in &patterns {
+            group.bench_with_input(BenchmarkId::new(*kind, name), &contents, |b, contents| {
+                b.iter(|| {
+                    let mut lines = 0;
+                    Searcher::default()
+                        .search_str(pattern, black_box(contents), &mut |_: &Match| {
+                            lines += 1;
+                            Ok(true)
+                        })
+                        .unwrap();
+                    lines
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, free_functions, searcher);
+criterion_main!(benches);
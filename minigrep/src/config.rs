@@ -0,0 +1,1043 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use dotenv::dotenv;
+use encoding_rs::Encoding;
+
+use toml::Value;
+
+use crate::{
+    fields::FieldQuery,
+    settings::{self, Source},
+    walk, Boundary, ColorChoice, OutputFormat, Pattern,
+};
+
+pub const USAGE: &str = "\
+Usage: minigrep [search] [OPTIONS] QUERY PATH...
+       minigrep [search] [OPTIONS] -e QUERY... -f FILE... PATH...
+       minigrep [search] [OPTIONS] --field PATH=VALUE... PATH...
+       minigrep [OPTIONS] --interactive [-e QUERY] FILE
+       minigrep index [OPTIONS] DIR...
+
+Search each PATH (a file, a directory, or - for standard input) for lines
+containing QUERY. Input is read line by line, so files of any size work, and
+gzip, bzip2 and zstd input is recognized by its first bytes, whatever the file
+is called, and decompressed as it is read.
+
+`minigrep index DIR` saves a trigram index of DIR in DIR/.minigrep-index;
+running it again only rereads files that changed. Searches with --index skip
+the files in DIR that the index shows can't match. `index` is only taken as
+the subcommand when every argument after it is an existing directory; to
+search directories for the word, use `minigrep search index DIR...`.
+
+Options:
+  -i, --ignore-case         Match case-insensitively
+  -s, --case-sensitive      Match case-sensitively (the default)
+  -S, --smart-case          Match case-insensitively unless QUERY contains an
+                            uppercase letter
+      --regex               Treat QUERY as a regular expression
+      --fuzzy K             Match QUERY allowing up to K typos (inserted,
+                            deleted or changed characters); the number of
+                            edits is shown as ~N after the column
+  -e, --regexp QUERY        Search for QUERY; repeat to match any of several
+  -f, --file FILE           Search for every line of FILE (blank lines are
+                            ignored)
+      --field PATH=VALUE    Read lines as JSON objects and select those whose
+                            field at PATH (keys joined by dots, as in
+                            req.user) is VALUE, or contains it with
+                            PATH~VALUE; repeat to require several fields.
+                            Lines that aren't JSON are searched for VALUE
+  -w, --word-regexp         Only match whole words
+  -x, --line-regexp         Only match whole lines (wins over -w)
+  -U, --multiline           Let matches span lines: \\n in a --regex matches a
+                            line break, and (?s) lets . match one too. Each
+                            file is read whole, and a match prints every line
+                            it covers, numbered START-END on the first
+  -v, --invert-match        Select lines that don't match
+  -c, --count               Print the number of selected lines per file
+  -l, --files-with-matches  Print only the names of files with a selected line
+  -m, --max-count N         Stop reading a file after N selected lines
+  -r, --replace TEXT        Show matching lines with every match replaced by
+                            TEXT ($1 or ${name} expand to regex groups)
+      --write               Apply --replace to the files in place
+      --dry-run             Print the diff --write would apply instead
+  -A, --after-context N     Show N lines after each match
+  -B, --before-context N    Show N lines before each match
+  -C, --context N           Show N lines before and after each match
+  -g, --glob GLOB           Only search files matching GLOB in directories, or
+                            skip them if GLOB starts with ! (repeatable)
+      --no-ignore           Don't honor .gitignore and .ignore files
+      --hidden              Search hidden files and directories
+      --index               Use the index saved by `minigrep index` to skip
+                            files in directories that can't match
+      --encoding LABEL      Read input as LABEL (e.g. latin1, utf-16le,
+                            shift_jis) instead of detecting each file's
+                            encoding from its byte order mark or contents
+  -z, --search-zip          Decompress any input starting with gzip, bzip2 or
+                            zstd magic bytes; without it, files not named
+                            .gz, .bz2 or .zst need a full format header
+  -j, --threads N           Search up to N files at once (default: one per CPU)
+      --color WHEN          Highlight matches: always, never or auto (the
+                            default, only when stdout is a terminal)
+      --output FORMAT       Print results as text (the default) or json, one
+                            JSON object per match plus a final summary
+      --follow              Keep FILE open after searching it, like tail -f,
+                            searching lines as they are appended; a file
+                            truncated or replaced by log rotation is reread
+                            from the start
+      --interactive         Search FILE in a full-screen view that updates as
+                            the query is typed; Up and Down pick a line,
+                            Enter prints it and Esc quits
+      --print-config        Print the settings in effect, and where each one
+                            comes from, then exit
+  -h, --help                Print this help and exit
+  -V, --version             Print the version and exit
+
+Environment:
+  CASE_SENSITIVE            Case mode used when none of -i, -s or -S is given
+                            (true, false or smart, also read from .env)
+
+Configuration files:
+  Defaults are read from $XDG_CONFIG_HOME/minigrep/minigrep.toml (or
+  ~/.config/minigrep/minigrep.toml), then from minigrep.toml in the current
+  directory or the nearest parent that has one. Later files win, the
+  environment wins over both, and flags win over everything:
+
+    case = \"smart\"             # sensitive, insensitive or smart
+    color = \"never\"            # always, never or auto
+    output = \"json\"            # text or json
+    ignore = [\"*.min.js\"]      # globs to skip, like --glob '!GLOB'";
+
+/// What minigrep was asked to do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    /// Search the paths; the default, also spelled `minigrep search`.
+    Search,
+    /// `minigrep index`: build or refresh the trigram index of each path.
+    Index,
+}
+
+pub struct Config {
+    pub command: Command,
+    /// Every query to look for; a line matches when any of them hits.
+    pub patterns: Vec<String>,
+    pub file_names: Vec<String>,
+    pub case_sensitive: bool,
+    /// Set by `-S`: `case_sensitive` follows the query when it changes.
+    pub smart_case: bool,
+    pub use_regex: bool,
+    /// Set by `-w` and `-x`.
+    pub boundary: Boundary,
+    /// Maximum edit distance for `--fuzzy`.
+    pub fuzzy: Option<usize>,
+    /// `--field` queries; their values are also `patterns`.
+    pub fields: Vec<FieldQuery>,
+    /// Set by `-U`: match against whole inputs instead of single lines.
+    pub multiline: bool,
+    /// `patterns`, compiled.
+    pub pattern: Pattern,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub threads: usize,
+    pub output: OutputFormat,
+    pub invert_match: bool,
+    pub count: bool,
+    pub files_with_matches: bool,
+    pub max_count: Option<usize>,
+    pub color: ColorChoice,
+    pub globs: Vec<String>,
+    pub no_ignore: bool,
+    pub hidden: bool,
+    pub search_zip: bool,
+    /// Set by `--encoding`; `None` detects the encoding of each input.
+    pub encoding: Option<&'static Encoding>,
+    pub replace: Option<String>,
+    pub write: bool,
+    pub dry_run: bool,
+    pub interactive: bool,
+    pub follow: bool,
+    /// Narrow directory searches with a saved index (`--index`).
+    pub use_index: bool,
+}
+
+/// What the command line asks for. Only `Run` goes on to search or build
+/// an index; the others are answered by printing something and exiting.
+pub enum Invocation {
+    Run(Box<Config>),
+    /// `--help`, answered with [`USAGE`].
+    Help,
+    /// `--version`.
+    Version,
+    /// `--print-config`, carrying the description to print.
+    PrintConfig(String),
+}
+
+/// Everything that can go wrong while turning the command line into a
+/// [`Config`].
+#[derive(Debug)]
+pub enum ConfigError {
+    MissingArgument(&'static str),
+    MissingValue(String),
+    InvalidValue { name: String, value: String },
+    UnknownFlag(String),
+    InvalidPattern(regex::Error),
+    InvalidGlob(String, ignore::Error),
+    PatternFile(String, io::Error),
+    ConfigFile(String, String),
+    TooManyPatterns(aho_corasick::BuildError),
+    Conflict(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::MissingArgument(name) => write!(f, "Not enough arguments! Missing {}.", name),
+            ConfigError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            ConfigError::InvalidValue { name, value } => write!(f, "Invalid value {:?} for {}", value, name),
+            ConfigError::UnknownFlag(flag) => write!(f, "Unknown option {}", flag),
+            ConfigError::InvalidPattern(e) => write!(f, "Invalid regular expression: {}", e),
+            ConfigError::InvalidGlob(glob, e) => write!(f, "Invalid glob {:?}: {}", glob, e),
+            ConfigError::PatternFile(path, e) => write!(f, "Can't read patterns from {}: {}", path, e),
+            ConfigError::ConfigFile(path, message) => write!(f, "Invalid configuration in {}: {}", path, message),
+            ConfigError::TooManyPatterns(e) => write!(f, "Can't search for these patterns: {}", e),
+            ConfigError::Conflict(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::InvalidPattern(e) => Some(e),
+            ConfigError::InvalidGlob(_, e) => Some(e),
+            ConfigError::PatternFile(_, e) => Some(e),
+            ConfigError::TooManyPatterns(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Config {
+    /// Parses the command line on top of the settings in the user's and
+    /// the project's `minigrep.toml`.
+    /// The `CASE_SENSITIVE` environment variable (or `.env`) applies when
+    /// no case flag is given.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(args: &[String]) -> Result<Invocation, ConfigError> {
+        Config::parse(args, &settings::default_files(), case_mode_from_env)
+    }
+
+    /// Like [`Config::new`], reading settings from `files` instead and
+    /// leaving the environment out, so the result only depends on the
+    /// arguments.
+    pub fn with_files(args: &[String], files: &[PathBuf]) -> Result<Invocation, ConfigError> {
+        Config::parse(args, files, || Ok(None))
+    }
+
+    /// `env_case_mode` is only asked for a case mode when no case flag was
+    /// given.
+    fn parse(
+        args: &[String],
+        files: &[PathBuf],
+        env_case_mode: impl FnOnce() -> Result<Option<CaseMode>, ConfigError>,
+    ) -> Result<Invocation, ConfigError> {
+        let mut case_mode = None;
+        let mut use_regex = false;
+        let mut fuzzy = None;
+        let mut word = false;
+        let mut line = false;
+        let mut patterns = Vec::new();
+        let mut pattern_given = false;
+        let mut fields = Vec::new();
+        let mut before_context = 0;
+        let mut after_context = 0;
+        let mut threads = None;
+        let mut output = None;
+        let mut invert_match = false;
+        let mut count = false;
+        let mut files_with_matches = false;
+        let mut max_count = None;
+        let mut color = None;
+        let mut globs = Vec::new();
+        let mut no_ignore = false;
+        let mut hidden = false;
+        let mut search_zip = false;
+        let mut encoding = None;
+        let mut replace = None;
+        let mut write = false;
+        let mut dry_run = false;
+        let mut interactive = false;
+        let mut follow = false;
+        let mut multiline = false;
+        let mut print_config = false;
+        let mut use_index = false;
+        let mut positional = Vec::new();
+
+        let mut args: VecDeque<String> = args.iter().skip(1).cloned().collect();
+
+        let index_first = args.front().is_some_and(|arg| arg == "index");
+        if args.front().is_some_and(|arg| arg == "search") {
+            args.pop_front();
+        }
+
+        while let Some(arg) = args.pop_front() {
+            if arg == "--" {
+                positional.extend(args.drain(..));
+                break;
+            }
+
+            let (flag, inline_value) = split_flag(&arg);
+
+            match flag {
+                "-h" | "--help" => return Ok(Invocation::Help),
+                "-V" | "--version" => return Ok(Invocation::Version),
+                "-i" | "--ignore-case" => case_mode = Some(CaseMode::Insensitive),
+                "-s" | "--case-sensitive" => case_mode = Some(CaseMode::Sensitive),
+                "-S" | "--smart-case" => case_mode = Some(CaseMode::Smart),
+                "--regex" => use_regex = true,
+                "--fuzzy" => {
+                    fuzzy = Some(parse_number(flag, take_value(flag, inline_value, &mut args)?)?);
+                    continue;
+                }
+                "-e" | "--regexp" => {
+                    patterns.push(take_value(flag, inline_value, &mut args)?);
+                    pattern_given = true;
+                    continue;
+                }
+                "-f" | "--file" => {
+                    let path = take_value(flag, inline_value, &mut args)?;
+                    let contents = fs::read_to_string(&path).map_err(|e| ConfigError::PatternFile(path, e))?;
+
+                    patterns.extend(contents.lines().filter(|line| !line.is_empty()).map(String::from));
+                    pattern_given = true;
+                    continue;
+                }
+                "--field" => {
+                    let spec = take_value(flag, inline_value, &mut args)?;
+                    let query = FieldQuery::parse(&spec).ok_or(ConfigError::InvalidValue { name: flag.to_string(), value: spec })?;
+                    fields.push(query);
+                    continue;
+                }
+                "-w" | "--word-regexp" => word = true,
+                "-x" | "--line-regexp" => line = true,
+                "-U" | "--multiline" => multiline = true,
+                "-v" | "--invert-match" => invert_match = true,
+                "-c" | "--count" => count = true,
+                "-l" | "--files-with-matches" => files_with_matches = true,
+                "-m" | "--max-count" => {
+                    max_count = Some(parse_number(flag, take_value(flag, inline_value, &mut args)?)?);
+                    continue;
+                }
+                "-A" | "--after-context" => {
+                    after_context = parse_number(flag, take_value(flag, inline_value, &mut args)?)?;
+                    continue;
+                }
+                "-B" | "--before-context" => {
+                    before_context = parse_number(flag, take_value(flag, inline_value, &mut args)?)?;
+                    continue;
+                }
+                "-C" | "--context" => {
+                    let lines = parse_number(flag, take_value(flag, inline_value, &mut args)?)?;
+                    before_context = lines;
+                    after_context = lines;
+                    continue;
+                }
+                "--color" | "--colour" => {
+                    color = match take_value(flag, inline_value, &mut args)?.as_str() {
+                        "always" => Some(ColorChoice::Always),
+                        "never" => Some(ColorChoice::Never),
+                        "auto" => Some(ColorChoice::Auto),
+                        value => {
+                            let value = value.to_string();
+                            return Err(ConfigError::InvalidValue { name: flag.to_string(), value });
+                        }
+                    };
+                    continue;
+                }
+                "--output" => {
+                    output = match take_value(flag, inline_value, &mut args)?.as_str() {
+                        "text" => Some(OutputFormat::Text),
+                        "json" => Some(OutputFormat::Json),
+                        value => {
+                            let value = value.to_string();
+                            return Err(ConfigError::InvalidValue { name: flag.to_string(), value });
+                        }
+                    };
+                    continue;
+                }
+                "-g" | "--glob" => {
+                    let glob = take_value(flag, inline_value, &mut args)?;
+                    walk::validate_glob(&glob).map_err(|e| ConfigError::InvalidGlob(glob.clone(), e))?;
+                    globs.push(glob);
+                    continue;
+                }
+                "--no-ignore" => no_ignore = true,
+                "--hidden" => hidden = true,
+                "-z" | "--search-zip" => search_zip = true,
+                "--encoding" => {
+                    let label = take_value(flag, inline_value, &mut args)?;
+                    encoding = match Encoding::for_label(label.as_bytes()) {
+                        _ if label == "auto" => None,
+                        Some(found) => Some(found),
+                        None => return Err(ConfigError::InvalidValue { name: flag.to_string(), value: label }),
+                    };
+                    continue;
+                }
+                "-r" | "--replace" => {
+                    replace = Some(take_value(flag, inline_value, &mut args)?);
+                    continue;
+                }
+                "--write" => write = true,
+                "--dry-run" => dry_run = true,
+                "--interactive" => interactive = true,
+                "--follow" => follow = true,
+                "--print-config" => print_config = true,
+                "--index" => use_index = true,
+                "-j" | "--threads" => {
+                    let value = take_value(flag, inline_value, &mut args)?;
+                    match parse_number(flag, value.clone())? {
+                        0 => return Err(ConfigError::InvalidValue { name: flag.to_string(), value }),
+                        count => threads = Some(count),
+                    }
+                    continue;
+                }
+                _ if flag.starts_with('-') && flag != "-" => return Err(ConfigError::UnknownFlag(arg)),
+                _ => {
+                    positional.push(arg);
+                    continue;
+                }
+            }
+
+            // A boolean short flag followed by more characters is a cluster
+            // such as `-is`; queue the rest to be parsed as its own flag.
+            if let Some(rest) = inline_value {
+                if flag.starts_with("--") {
+                    return Err(ConfigError::UnknownFlag(arg));
+                }
+                args.push_front(format!("-{}", rest));
+            }
+        }
+
+        // Flags win over the environment, which wins over the files.
+        let file_settings = settings::load(files)?;
+
+        let (case_mode, case_source) = match case_mode {
+            Some(case_mode) => (case_mode, Source::CommandLine),
+            None => match env_case_mode()? {
+                Some(case_mode) => (case_mode, Source::Environment("CASE_SENSITIVE")),
+                None => file_settings.case.unwrap_or((CaseMode::Sensitive, Source::Default)),
+            },
+        };
+        let (color, color_source) = match color {
+            Some(color) => (color, Source::CommandLine),
+            None => file_settings.color.unwrap_or((ColorChoice::Auto, Source::Default)),
+        };
+        let (output, output_source) = match output {
+            Some(output) => (output, Source::CommandLine),
+            None => file_settings.output.unwrap_or((OutputFormat::Text, Source::Default)),
+        };
+
+        if print_config {
+            let ignore: Vec<Value> = file_settings.ignore.iter().map(|(glob, _)| Value::from(glob.as_str())).collect();
+            let mut ignore_sources: Vec<String> = Vec::new();
+            for (_, source) in &file_settings.ignore {
+                if !ignore_sources.contains(&source.to_string()) {
+                    ignore_sources.push(source.to_string());
+                }
+            }
+            if ignore_sources.is_empty() {
+                ignore_sources.push(Source::Default.to_string());
+            }
+
+            let mut described = vec![
+                ("case", Value::from(case_mode.name()), case_source.to_string()),
+                ("color", Value::from(color.name()), color_source.to_string()),
+                ("output", Value::from(output.name()), output_source.to_string()),
+                ("ignore", Value::from(ignore), ignore_sources.join(", ")),
+            ];
+            if !globs.is_empty() {
+                let globs: Vec<Value> = globs.iter().map(|glob| Value::from(glob.as_str())).collect();
+                described.push(("glob", Value::from(globs), Source::CommandLine.to_string()));
+            }
+
+            return Ok(Invocation::PrintConfig(settings::describe(&file_settings.files, &described)));
+        }
+
+        // Ignore globs from the files go first so --glob can override them.
+        let globs: Vec<String> = file_settings.ignore.into_iter().map(|(glob, _)| format!("!{}", glob)).chain(globs).collect();
+
+        // Without -e or -f, the first positional argument is the query,
+        // except in --interactive mode where it is typed in instead, and
+        // when indexing, which doesn't need one.
+        // --field values stand in for the query, and for lines that
+        // aren't JSON they are what is searched for.
+        if !fields.is_empty() {
+            if pattern_given {
+                return Err(ConfigError::Conflict("--field can't be combined with -e or -f"));
+            }
+            patterns = fields.iter().map(|query| query.value.clone()).collect();
+            pattern_given = true;
+        }
+
+        // `index` is only the subcommand when everything after it is a
+        // directory to index, so `minigrep index notes.txt` still searches
+        // for "index".
+        let command = if index_first && positional.len() > 1 && positional[1..].iter().all(|name| Path::new(name).is_dir()) {
+            positional.remove(0);
+            Command::Index
+        } else {
+            Command::Search
+        };
+
+        let mut positional = positional.into_iter();
+        if command == Command::Index {
+            pattern_given = true;
+        }
+        if !pattern_given && interactive {
+            patterns.push(String::new());
+        } else if !pattern_given {
+            patterns.push(positional.next().ok_or(ConfigError::MissingArgument("QUERY"))?);
+        }
+        let file_names: Vec<String> = positional.collect();
+
+        if file_names.is_empty() {
+            let name = if command == Command::Index { "DIR" } else { "PATH" };
+            return Err(ConfigError::MissingArgument(name));
+        }
+
+        if interactive && (file_names.len() > 1 || file_names[0] == "-") {
+            return Err(ConfigError::Conflict("--interactive searches a single FILE, not standard input"));
+        }
+
+        // The prompt holds a single query to start from.
+        if interactive && patterns.len() > 1 {
+            return Err(ConfigError::Conflict("--interactive takes at most one pattern from -e or -f"));
+        }
+
+        // The picker shows lines as they are, so options that change what
+        // is printed don't apply. An output format from a settings file is
+        // just not used.
+        if interactive
+            && (invert_match
+                || max_count.is_some()
+                || count
+                || files_with_matches
+                || replace.is_some()
+                || (output != OutputFormat::Text && output_source == Source::CommandLine))
+        {
+            return Err(ConfigError::Conflict("--interactive can't be combined with -v, -m, -c, -l, --replace or --output json"));
+        }
+
+        if follow && (file_names.len() > 1 || file_names[0] == "-" || Path::new(&file_names[0]).is_dir()) {
+            return Err(ConfigError::Conflict("--follow watches a single FILE, not standard input or a directory"));
+        }
+
+        if follow && (count || write || dry_run || interactive) {
+            return Err(ConfigError::Conflict("--follow can't be combined with -c, --write, --dry-run or --interactive"));
+        }
+
+        if multiline && (invert_match || fuzzy.is_some() || follow || interactive || write || dry_run) {
+            return Err(ConfigError::Conflict(
+                "--multiline can't be combined with -v, --fuzzy, --follow, --interactive, --write or --dry-run",
+            ));
+        }
+
+        if !fields.is_empty() && (fuzzy.is_some() || multiline || interactive || replace.is_some()) {
+            return Err(ConfigError::Conflict("--field can't be combined with --fuzzy, --multiline, --interactive or --replace"));
+        }
+
+        if (write || dry_run) && replace.is_none() {
+            return Err(ConfigError::Conflict("--write and --dry-run need --replace"));
+        }
+
+        if (write || dry_run) && (max_count.is_some() || invert_match || count || files_with_matches) {
+            return Err(ConfigError::Conflict("--write and --dry-run can't be combined with -m, -v, -c or -l"));
+        }
+
+        if use_regex && fuzzy.is_some() {
+            return Err(ConfigError::Conflict("--fuzzy can't be combined with --regex"));
+        }
+
+        if (word || line) && fuzzy.is_some() {
+            return Err(ConfigError::Conflict("--fuzzy can't be combined with -w or -x"));
+        }
+
+        let boundary = if line {
+            Boundary::Line
+        } else if word {
+            Boundary::Word
+        } else {
+            Boundary::Substring
+        };
+
+        let threads = threads.unwrap_or_else(|| thread::available_parallelism().map_or(1, |count| count.get()));
+
+        let case_sensitive = match case_mode {
+            CaseMode::Sensitive => true,
+            CaseMode::Insensitive => false,
+            CaseMode::Smart => patterns.iter().any(|pattern| has_uppercase(pattern, use_regex)),
+        };
+
+        let pattern = compile(&patterns, &fields, use_regex, fuzzy, case_sensitive, boundary)?;
+
+        Ok(Invocation::Run(Box::new(Config {
+            command,
+            patterns,
+            file_names,
+            case_sensitive,
+            smart_case: matches!(case_mode, CaseMode::Smart),
+            use_regex,
+            boundary,
+            fuzzy,
+            fields,
+            multiline,
+            pattern,
+            before_context,
+            after_context,
+            threads,
+            output,
+            invert_match,
+            count,
+            files_with_matches,
+            max_count,
+            color,
+            globs,
+            no_ignore,
+            hidden,
+            search_zip,
+            encoding,
+            replace,
+            write,
+            dry_run,
+            interactive,
+            follow,
+            use_index,
+        })))
+    }
+
+    /// Replaces the patterns with a single `query`, recompiling it the same
+    /// way [`Config::new`] would.
+    pub fn set_query(&mut self, query: &str) -> Result<(), ConfigError> {
+        let patterns = vec![query.to_string()];
+
+        if self.smart_case {
+            self.case_sensitive = has_uppercase(query, self.use_regex);
+        }
+
+        self.pattern = compile(&patterns, &self.fields, self.use_regex, self.fuzzy, self.case_sensitive, self.boundary)?;
+        self.patterns = patterns;
+
+        Ok(())
+    }
+}
+
+fn compile(
+    patterns: &[String],
+    fields: &[FieldQuery],
+    use_regex: bool,
+    fuzzy: Option<usize>,
+    case_sensitive: bool,
+    boundary: Boundary,
+) -> Result<Pattern, ConfigError> {
+    if !fields.is_empty() {
+        // `=` values are compiled as if for `-x`.
+        let mut queries = Vec::new();
+        for query in fields {
+            let boundary = if query.exact { Boundary::Line } else { boundary };
+            let pattern = compile(std::slice::from_ref(&query.value), &[], use_regex, fuzzy, case_sensitive, boundary)?;
+            queries.push((query.clone(), pattern));
+        }
+
+        let fallback = compile(patterns, &[], use_regex, fuzzy, case_sensitive, boundary)?;
+        Ok(Pattern::fields(queries, fallback))
+    } else if let Some(max_distance) = fuzzy {
+        Ok(Pattern::fuzzy(patterns, max_distance, case_sensitive))
+    } else if use_regex {
+        Pattern::regex(patterns, case_sensitive, boundary).map_err(ConfigError::InvalidPattern)
+    } else {
+        Pattern::literal(patterns, case_sensitive, boundary).map_err(ConfigError::TooManyPatterns)
+    }
+}
+
+/// Splits `--flag=value` and `-Fvalue` into the flag and its inline value.
+fn split_flag(arg: &str) -> (&str, Option<&str>) {
+    if let Some(long) = arg.strip_prefix("--") {
+        match long.find('=') {
+            Some(index) => (&arg[..index + 2], Some(&arg[index + 3..])),
+            None => (arg, None),
+        }
+    } else if arg.starts_with('-') && arg.len() > 2 && arg.is_char_boundary(2) {
+        (&arg[..2], Some(&arg[2..]))
+    } else {
+        (arg, None)
+    }
+}
+
+fn take_value(flag: &str, inline_value: Option<&str>, args: &mut VecDeque<String>) -> Result<String, ConfigError> {
+    match inline_value {
+        Some(value) => Ok(value.to_string()),
+        None => args.pop_front().ok_or_else(|| ConfigError::MissingValue(flag.to_string())),
+    }
+}
+
+fn parse_number(flag: &str, value: String) -> Result<usize, ConfigError> {
+    value.parse().map_err(|_| ConfigError::InvalidValue { name: flag.to_string(), value })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CaseMode {
+    Sensitive,
+    Insensitive,
+    /// Sensitive only when the query has an uppercase letter.
+    Smart,
+}
+
+impl CaseMode {
+    fn name(self) -> &'static str {
+        match self {
+            CaseMode::Sensitive => "sensitive",
+            CaseMode::Insensitive => "insensitive",
+            CaseMode::Smart => "smart",
+        }
+    }
+}
+
+impl ColorChoice {
+    fn name(self) -> &'static str {
+        match self {
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+            ColorChoice::Auto => "auto",
+        }
+    }
+}
+
+impl OutputFormat {
+    fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+/// Reads the `CASE_SENSITIVE` variable (loaded from `.env` when present),
+/// used when no case flag is given. `None` when it isn't set.
+fn case_mode_from_env() -> Result<Option<CaseMode>, ConfigError> {
+    dotenv().ok();
+
+    match std::env::var("CASE_SENSITIVE") {
+        Ok(value) => match value.to_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(Some(CaseMode::Sensitive)),
+            "false" | "0" | "no" => Ok(Some(CaseMode::Insensitive)),
+            "smart" => Ok(Some(CaseMode::Smart)),
+            _ => Err(ConfigError::InvalidValue { name: "CASE_SENSITIVE".to_string(), value }),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+/// Whether `query` contains an uppercase letter. In a regex, escaped
+/// characters like `\W` or `\S` don't count.
+fn has_uppercase(query: &str, is_regex: bool) -> bool {
+    let mut chars = query.chars();
+
+    while let Some(c) = chars.next() {
+        if is_regex && c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, args, run};
+
+    #[test]
+    fn flags_and_positionals() {
+        let config = test_support::config(&["minigrep", "-i", "--context=2", "-B1", "query", "file.txt"]);
+
+        assert_eq!(vec!["query"], config.patterns);
+        assert_eq!(vec!["file.txt"], config.file_names);
+        assert!(!config.case_sensitive);
+        assert_eq!(1, config.before_context);
+        assert_eq!(2, config.after_context);
+    }
+
+    #[test]
+    fn several_paths_and_threads() {
+        let config = test_support::config(&["minigrep", "-j", "3", "query", "a.txt", "src", "-"]);
+
+        assert_eq!(vec!["a.txt", "src", "-"], config.file_names);
+        assert_eq!(3, config.threads);
+        assert!(Config::with_files(&args(&["minigrep", "--threads=0", "q", "f"]), &[]).is_err());
+    }
+
+    #[test]
+    fn output_modes() {
+        let config = test_support::config(&["minigrep", "-vcl", "-m5", "q", "f"]);
+
+        assert!(config.invert_match);
+        assert!(config.count);
+        assert!(config.files_with_matches);
+        assert_eq!(Some(5), config.max_count);
+    }
+
+    #[test]
+    fn several_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("patterns");
+        fs::write(&path, "warn\n\nfatal\n").unwrap();
+
+        let config = test_support::config(&["minigrep", "-e", "error", "-f", path.to_str().unwrap(), "log.txt", "-"]);
+
+        assert_eq!(vec!["error", "warn", "fatal"], config.patterns);
+        assert_eq!(vec!["log.txt", "-"], config.file_names);
+        assert!(matches!(
+            Config::with_files(&args(&["minigrep", "-f", "/does/not/exist", "log.txt"]), &[]),
+            Err(ConfigError::PatternFile(..))
+        ));
+    }
+
+    #[test]
+    fn last_case_flag_wins() {
+        let config = test_support::config(&["minigrep", "-i", "--case-sensitive", "q", "f"]);
+        assert!(config.case_sensitive);
+
+        let config = test_support::config(&["minigrep", "-si", "q", "f"]);
+        assert!(!config.case_sensitive);
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(!test_support::config(&["minigrep", "-S", "error", "f"]).case_sensitive);
+        assert!(test_support::config(&["minigrep", "-S", "Error", "f"]).case_sensitive);
+        assert!(test_support::config(&["minigrep", "-S", "ÉTÉ", "f"]).case_sensitive);
+        assert!(!test_support::config(&["minigrep", "-S", "--regex", r"\Werror\S", "f"]).case_sensitive);
+        assert!(!test_support::config(&["minigrep", "-S", "-i", "Error", "f"]).case_sensitive);
+    }
+
+    #[test]
+    fn line_regexp_wins_over_word_regexp() {
+        assert_eq!(Boundary::Word, test_support::config(&["minigrep", "-w", "q", "f"]).boundary);
+        assert_eq!(Boundary::Line, test_support::config(&["minigrep", "-xw", "q", "f"]).boundary);
+        assert_eq!(Boundary::Substring, test_support::config(&["minigrep", "q", "f"]).boundary);
+    }
+
+    #[test]
+    fn double_dash_ends_flags() {
+        let config = test_support::config(&["minigrep", "--", "-i", "file.txt"]);
+
+        assert_eq!(vec!["-i"], config.patterns);
+    }
+
+    #[test]
+    fn structured_errors() {
+        assert!(matches!(Config::with_files(&args(&["minigrep"]), &[]), Err(ConfigError::MissingArgument("QUERY"))));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "q"]), &[]), Err(ConfigError::MissingArgument("PATH"))));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--frobnicate", "q", "f"]), &[]), Err(ConfigError::UnknownFlag(_))));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "q", "f", "-A"]), &[]), Err(ConfigError::MissingValue(_))));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "-C", "many", "q", "f"]), &[]), Err(ConfigError::InvalidValue { .. })));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--encoding", "klingon", "q", "f"]), &[]), Err(ConfigError::InvalidValue { .. })));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--help"]), &[]), Ok(Invocation::Help)));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "-V"]), &[]), Ok(Invocation::Version)));
+    }
+
+    #[test]
+    fn fuzzy_is_literal_only() {
+        assert_eq!(Some(2), test_support::config(&["minigrep", "--fuzzy=2", "q", "f"]).fuzzy);
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--fuzzy", "1", "--regex", "q", "f"]), &[]), Err(ConfigError::Conflict(_))));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--fuzzy", "1", "-w", "q", "f"]), &[]), Err(ConfigError::Conflict(_))));
+    }
+
+    #[test]
+    fn interactive_takes_a_single_file() {
+        let mut config = test_support::config(&["minigrep", "--interactive", "-S", "notes.txt"]);
+        assert_eq!(vec![""], config.patterns);
+        assert_eq!(vec!["notes.txt"], config.file_names);
+
+        config.set_query("Todo").unwrap();
+        assert!(config.case_sensitive);
+        config.set_query("todo").unwrap();
+        assert!(!config.case_sensitive);
+
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--interactive", "a", "b"]), &[]), Err(ConfigError::Conflict(_))));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--interactive", "-"]), &[]), Err(ConfigError::Conflict(_))));
+
+        let config = test_support::config(&["minigrep", "--interactive", "-e", "todo", "notes.txt"]);
+        assert_eq!(vec!["todo"], config.patterns);
+        let several = Config::with_files(&args(&["minigrep", "--interactive", "-e", "a", "-e", "b", "notes.txt"]), &[]);
+        assert!(matches!(several, Err(ConfigError::Conflict(_))));
+
+        for flags in [&["-v"][..], &["-m", "1"], &["-c"], &["-l"], &["-r", "x"], &["--output", "json"]] {
+            let args: Vec<&str> = ["minigrep", "--interactive"].iter().chain(flags).chain(&["notes.txt"]).copied().collect();
+            assert!(matches!(Config::with_files(&self::args(&args), &[]), Err(ConfigError::Conflict(_))), "{:?}", flags);
+        }
+    }
+
+    #[test]
+    fn subcommands() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().to_str().unwrap();
+
+        let config = test_support::config(&["minigrep", "index", "--hidden", docs]);
+        assert_eq!(Command::Index, config.command);
+        assert!(config.patterns.is_empty());
+        assert_eq!(vec![docs], config.file_names);
+
+        let config = test_support::config(&["minigrep", "search", "--index", "index", docs]);
+        assert_eq!(Command::Search, config.command);
+        assert!(config.use_index);
+        assert_eq!(vec!["index"], config.patterns);
+
+        // Anything but directories after `index` makes it the query.
+        for paths in [&["notes.txt"][..], &[docs, "notes.txt"], &["-"]] {
+            let args: Vec<&str> = ["minigrep", "index"].iter().chain(paths).copied().collect();
+            let config = test_support::config(&args);
+            assert_eq!((Command::Search, vec!["index".to_string()]), (config.command, config.patterns), "{:?}", paths);
+        }
+        assert!(matches!(Config::with_files(&args(&["minigrep", "index"]), &[]), Err(ConfigError::MissingArgument("PATH"))));
+    }
+
+    #[test]
+    fn field_queries() {
+        let config = test_support::config(&["minigrep", "--field", "level=error", "--field=req.user~alice", "app.log"]);
+        assert_eq!(vec!["error", "alice"], config.patterns);
+        assert_eq!(vec!["app.log"], config.file_names);
+        assert_eq!(vec!["req", "user"], config.fields[1].path);
+
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--field", "level", "f"]), &[]), Err(ConfigError::InvalidValue { .. })));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--field", "a=b", "-e", "q", "f"]), &[]), Err(ConfigError::Conflict(_))));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--field", "a=b", "-r", "x", "f"]), &[]), Err(ConfigError::Conflict(_))));
+    }
+
+    #[test]
+    fn multiline_conflicts() {
+        assert!(test_support::config(&["minigrep", "-U", "--regex", r"a\nb", "f"]).multiline);
+        assert!(matches!(Config::with_files(&args(&["minigrep", "-Uv", "q", "f"]), &[]), Err(ConfigError::Conflict(_))));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--multiline", "--fuzzy", "1", "q", "f"]), &[]), Err(ConfigError::Conflict(_))));
+    }
+
+    #[test]
+    fn follow_takes_a_single_file() {
+        assert!(test_support::config(&["minigrep", "--follow", "q", "app.log"]).follow);
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--follow", "q", "a", "b"]), &[]), Err(ConfigError::Conflict(_))));
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--follow", "-c", "q", "a"]), &[]), Err(ConfigError::Conflict(_))));
+
+        let dir = tempfile::tempdir().unwrap();
+        let followed = Config::with_files(&args(&["minigrep", "--follow", "q", dir.path().to_str().unwrap()]), &[]);
+        assert!(matches!(followed, Err(ConfigError::Conflict(_))));
+    }
+
+    fn settings_file(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(format!("{}.toml", name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn layered_settings_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = settings_file(dir.path(), "user", "case = \"insensitive\"\ncolor = \"never\"\nignore = [\"*.lock\"]\n");
+        let project = settings_file(dir.path(), "project", "case = \"smart\"\noutput = \"json\"\nignore = [\"target/\"]\n");
+        let files = [user.clone(), project.clone(), dir.path().join("missing.toml")];
+
+        let loaded = settings::load(&files).unwrap();
+        let from_files = run(Config::with_files(&args(&["minigrep", "q", "f"]), &files));
+        let uppercase = run(Config::with_files(&args(&["minigrep", "Q", "f"]), &files));
+        let from_flags = run(Config::with_files(&args(&["minigrep", "-i", "--color=always", "--output=text", "-g", "*.lock", "Q", "f"]), &files));
+
+        assert_eq!(vec![user, project.clone()], loaded.files);
+        assert_eq!(Some((CaseMode::Smart, Source::File(project))), loaded.case);
+        assert_eq!((false, true), (from_files.case_sensitive, from_files.smart_case));
+        assert_eq!((true, true), (uppercase.case_sensitive, uppercase.smart_case));
+        assert_eq!((ColorChoice::Never, OutputFormat::Json), (from_files.color, from_files.output));
+        assert_eq!(vec!["!*.lock", "!target/"], from_files.globs);
+        assert_eq!((false, false), (from_flags.case_sensitive, from_flags.smart_case));
+        assert_eq!((ColorChoice::Always, OutputFormat::Text), (from_flags.color, from_flags.output));
+        assert_eq!(vec!["!*.lock", "!target/", "*.lock"], from_flags.globs);
+    }
+
+    #[test]
+    fn environment_case_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [settings_file(dir.path(), "project", "case = \"smart\"\n")];
+        let invalid = || Err(ConfigError::InvalidValue { name: "CASE_SENSITIVE".to_string(), value: "loud".to_string() });
+
+        let config = run(Config::parse(&args(&["minigrep", "Q", "f"]), &files, || Ok(Some(CaseMode::Insensitive))));
+        assert_eq!((false, false), (config.case_sensitive, config.smart_case));
+
+        // With a case flag the environment isn't read at all.
+        assert!(run(Config::parse(&args(&["minigrep", "-s", "q", "f"]), &files, invalid)).case_sensitive);
+        assert!(matches!(Config::parse(&args(&["minigrep", "q", "f"]), &files, invalid), Err(ConfigError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn print_config_shows_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = settings_file(dir.path(), "print", "color = \"never\"\n");
+        let printed = Config::with_files(&args(&["minigrep", "--print-config", "--output", "json"]), std::slice::from_ref(&project));
+
+        let Ok(Invocation::PrintConfig(description)) = printed else {
+            panic!("expected the configuration to be printed");
+        };
+        let lines: Vec<&str> = description.lines().collect();
+        let setting = |line: &str| line.split_once("# ").map(|(setting, source)| (setting.trim_end().to_string(), source.to_string()));
+
+        assert_eq!(format!("# Read {}", project.display()), lines[0]);
+        assert_eq!(Some(("color = \"never\"".to_string(), project.display().to_string())), setting(lines[2]));
+        assert_eq!(Some(("output = \"json\"".to_string(), "command line".to_string())), setting(lines[3]));
+        assert_eq!(Some(("ignore = []".to_string(), "default".to_string())), setting(lines[4]));
+    }
+
+    #[test]
+    fn invalid_settings_files() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for (name, contents) in [("unknown", "colour = \"never\""), ("value", "case = \"loud\""), ("syntax", "case = ")] {
+            let path = settings_file(dir.path(), name, contents);
+            let result = Config::with_files(&args(&["minigrep", "q", "f"]), std::slice::from_ref(&path));
+
+            assert!(matches!(result, Err(ConfigError::ConfigFile(..))), "{}", name);
+        }
+    }
+
+    #[test]
+    fn write_needs_replace() {
+        assert!(matches!(Config::with_files(&args(&["minigrep", "--write", "q", "f"]), &[]), Err(ConfigError::Conflict(_))));
+        assert!(test_support::config(&["minigrep", "--dry-run", "-r", "x", "q", "f"]).dry_run);
+
+        for flag in ["-m1", "-v", "-c", "-l"] {
+            let result = Config::with_files(&args(&["minigrep", "--write", "-r", "x", flag, "q", "f"]), &[]);
+            assert!(matches!(result, Err(ConfigError::Conflict(_))), "{}", flag);
+        }
+    }
+
+    #[test]
+    fn invalid_glob_is_an_error() {
+        assert!(matches!(
+            Config::with_files(&args(&["minigrep", "--glob", "*.{rs", "q", "src"]), &[]),
+            Err(ConfigError::InvalidGlob(..))
+        ));
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        assert!(matches!(
+            Config::with_files(&args(&["minigrep", "--regex", "ERROR (", "file.txt"]), &[]),
+            Err(ConfigError::InvalidPattern(_))
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+
+/// A compression format recognized by its magic bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Zstd,
+}
+
+impl Compression {
+    /// Identifies the format from the first bytes of an input.
+    pub fn detect(header: &[u8]) -> Option<Compression> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Like [`Compression::detect`], but only for a full format header,
+    /// which text doesn't start with by accident: gzip's magic bytes with
+    /// its deflate method, bzip2's with a block size and the magic of a
+    /// block or of the end of the stream, or zstd's.
+    pub fn detect_header(header: &[u8]) -> Option<Compression> {
+        const BZIP2_BLOCK: [u8; 6] = [0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+        const BZIP2_END: [u8; 6] = [0x17, 0x72, 0x45, 0x38, 0x50, 0x90];
+
+        match Compression::detect(header)? {
+            Compression::Gzip if header.get(2) == Some(&8) => Some(Compression::Gzip),
+            Compression::Bzip2 => match header.get(3..) {
+                Some([b'1'..=b'9', rest @ ..]) if rest.starts_with(&BZIP2_BLOCK) || rest.starts_with(&BZIP2_END) => {
+                    Some(Compression::Bzip2)
+                }
+                _ => None,
+            },
+            Compression::Zstd => Some(Compression::Zstd),
+            Compression::Gzip => None,
+        }
+    }
+
+    /// The format a file name says it has: `.gz`, `.tgz`, `.bz2`, `.tbz2`,
+    /// `.zst` or `.tzst`.
+    pub fn from_extension(path: &Path) -> Option<Compression> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "gz" | "tgz" => Some(Compression::Gzip),
+            "bz2" | "tbz2" => Some(Compression::Bzip2),
+            "zst" | "tzst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Wraps `reader` in a streaming decoder when it starts with the magic
+/// bytes of a supported format, and hands it back untouched otherwise.
+///
+/// The file name is only a hint: standard input (`path` is `None`) and
+/// files named like compressed ones just need the magic bytes, while other
+/// files need a full header (see [`Compression::detect_header`]), so a text
+/// file that happens to start with `BZh` is read as it is. `force` (`-z`)
+/// takes the magic bytes of every input at their word.
+pub fn decompress<'a>(mut reader: Box<dyn BufRead + 'a>, path: Option<&Path>, force: bool) -> io::Result<Box<dyn BufRead + 'a>> {
+    let hinted = force || path.is_none_or(|path| Compression::from_extension(path).is_some());
+    let header = reader.fill_buf()?;
+    let compression = if hinted { Compression::detect(header) } else { Compression::detect_header(header) };
+
+    let reader: Box<dyn BufRead + 'a> = match compression {
+        Some(Compression::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Some(Compression::Bzip2) => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+        Some(Compression::Zstd) => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        None => reader,
+    };
+
+    Ok(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs::File, io::Read};
+
+    fn fixture(name: &str) -> Box<dyn BufRead> {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        Box::new(BufReader::new(File::open(path).unwrap()))
+    }
+
+    #[test]
+    fn every_format_decompresses() {
+        for name in ["log.txt.gz", "log.txt.bz2", "log.txt.zst"] {
+            let mut contents = String::new();
+            decompress(fixture(name), Some(Path::new(name)), false).unwrap().read_to_string(&mut contents).unwrap();
+
+            assert_eq!("first line\nERROR 500 inside a compressed log\nlast line\n", contents, "{}", name);
+        }
+    }
+
+    fn read(reader: io::Result<Box<dyn BufRead + '_>>) -> String {
+        let mut contents = String::new();
+        reader.unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn plain_input_passes_through() {
+        assert_eq!("plain text", read(decompress(Box::new(&b"plain text"[..]), None, false)));
+        assert_eq!("plain text", read(decompress(Box::new(&b"plain text"[..]), None, true)));
+        assert_eq!("BZh, plain", read(decompress(Box::new(&b"BZh, plain"[..]), Some(Path::new("notes.txt")), false)));
+    }
+
+    #[test]
+    fn magic_bytes_decide_and_names_are_a_hint() {
+        let path = Path::new("app.log.1");
+        let expected = "first line\nERROR 500 inside a compressed log\nlast line\n";
+
+        for name in ["log.txt.gz", "log.txt.bz2", "log.txt.zst"] {
+            assert_eq!(expected, read(decompress(fixture(name), Some(path), false)), "{}", name);
+            assert_eq!(expected, read(decompress(fixture(name), None, false)), "{}", name);
+        }
+
+        // Text starting with the short magic bytes is only taken for
+        // compressed input when named so, or with -z.
+        assert_eq!(Some(Compression::Bzip2), Compression::detect(b"BZh, plain"));
+        assert_eq!(None, Compression::detect_header(b"BZh, plain"));
+        assert_eq!(None, Compression::detect_header(b"\x1f\x8b"));
+        let mut named = decompress(Box::new(&b"BZh, plain"[..]), Some(Path::new("notes.bz2")), false).unwrap();
+        assert!(named.read_to_end(&mut Vec::new()).is_err());
+
+        assert_eq!(Some(Compression::Zstd), Compression::from_extension(Path::new("archive/app.LOG.ZST")));
+        assert_eq!(None, Compression::from_extension(Path::new("gz")));
+    }
+}
@@ -0,0 +1,115 @@
+use std::io::{self, BufRead, BufReader};
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+/// What the start of an input looks like.
+#[derive(Debug, PartialEq)]
+pub enum Detected {
+    Text(&'static Encoding),
+    /// Contains NUL bytes that aren't explained by UTF-16.
+    Binary,
+}
+
+/// Guesses the encoding of an input from its first bytes: a byte order
+/// mark wins, then UTF-16 without one (ASCII text with every other byte
+/// NUL), then UTF-8 if the bytes are valid, and otherwise the legacy
+/// encoding the text most looks like, such as windows-1252 for Latin-1.
+pub fn detect(sample: &[u8]) -> Detected {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return Detected::Text(encoding);
+    }
+
+    if let Some(encoding) = utf16_without_bom(sample) {
+        return Detected::Text(encoding);
+    }
+
+    if sample.contains(&0) {
+        return Detected::Binary;
+    }
+
+    // The sample may end partway through a character.
+    match std::str::from_utf8(sample) {
+        Ok(_) => Detected::Text(UTF_8),
+        Err(e) if e.error_len().is_none() => Detected::Text(UTF_8),
+        Err(_) => {
+            let mut detector = EncodingDetector::new();
+            detector.feed(sample, false);
+            Detected::Text(detector.guess(None, true))
+        }
+    }
+}
+
+fn utf16_without_bom(sample: &[u8]) -> Option<&'static Encoding> {
+    let pairs = sample.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+
+    let nul_at = |parity: usize| sample.iter().skip(parity).step_by(2).take(pairs).filter(|&&byte| byte == 0).count();
+    let (even, odd) = (nul_at(0), nul_at(1));
+
+    if odd * 4 >= pairs * 3 && even == 0 {
+        Some(UTF_16LE)
+    } else if even * 4 >= pairs * 3 && odd == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Wraps `reader` so it yields UTF-8, decoding from `encoding` when given
+/// (`--encoding`) and from the [`detect`]ed encoding otherwise. Invalid
+/// sequences become U+FFFD and byte order marks are dropped.
+///
+/// Also returns whether the input looks binary; it is then decoded as
+/// UTF-8 so it can still be searched. An explicit encoding means the input
+/// is text.
+pub fn decode<'a>(
+    mut reader: Box<dyn BufRead + 'a>,
+    encoding: Option<&'static Encoding>,
+) -> io::Result<(Box<dyn BufRead + 'a>, bool)> {
+    let (encoding, binary) = match encoding {
+        Some(encoding) => (encoding, false),
+        None => match detect(reader.fill_buf()?) {
+            Detected::Text(encoding) => (encoding, false),
+            Detected::Binary => (UTF_8, true),
+        },
+    };
+
+    let decoder = DecodeReaderBytesBuilder::new().encoding(Some(encoding)).bom_override(true).build(reader);
+
+    Ok((Box::new(BufReader::new(decoder)), binary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn decoded(bytes: &'static [u8], encoding: Option<&'static Encoding>) -> (String, bool) {
+        let (mut reader, binary) = decode(Box::new(bytes), encoding).unwrap();
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        (text, binary)
+    }
+
+    #[test]
+    fn detects_boms_utf16_and_legacy_text() {
+        assert_eq!(Detected::Text(UTF_16LE), detect(b"\xff\xfeh\0i\0"));
+        assert_eq!(Detected::Text(UTF_16BE), detect(b"\0h\0i\0!"));
+        assert_eq!(Detected::Text(UTF_16LE), detect(b"h\0i\0!\0"));
+        assert_eq!(Detected::Text(UTF_8), detect("caf\u{e9}".as_bytes()));
+        assert_eq!(Detected::Text(encoding_rs::WINDOWS_1252), detect(b"caf\xe9 cr\xe8me br\xfbl\xe9e, na\xefve"));
+        assert_eq!(Detected::Binary, detect(b"\x7fELF\x02\x01\x01\0\0\0\0"));
+    }
+
+    #[test]
+    fn transcodes_to_utf8() {
+        assert_eq!(("hi\n".to_string(), false), decoded(b"\xff\xfeh\0i\0\n\0", None));
+        assert_eq!(("caf\u{e9} cr\u{e8}me br\u{fb}l\u{e9}e".to_string(), false), decoded(b"caf\xe9 cr\xe8me br\xfbl\xe9e", None));
+        assert_eq!(("\u{441}\u{43b}\u{43e}\u{432}\u{43e}".to_string(), false), decoded(b"\xf1\xeb\xee\xe2\xee", Some(encoding_rs::WINDOWS_1251)));
+        assert_eq!(("ab\0\u{fffd}".to_string(), true), decoded(b"ab\0\xff", None));
+    }
+}
@@ -0,0 +1,172 @@
+use std::ops::Range;
+
+use serde_json::Value;
+
+use crate::Pattern;
+
+/// One `--field` query on JSON-lines input: `PATH=VALUE` wants the field
+/// to be VALUE, `PATH~VALUE` only wants it to contain a hit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldQuery {
+    /// Keys from the top-level object down; numbers also index arrays.
+    pub path: Vec<String>,
+    /// `=` rather than `~`.
+    pub exact: bool,
+    pub value: String,
+}
+
+impl FieldQuery {
+    /// Parses `level=error` or `req.user~alice`, splitting at the first `=`
+    /// or `~`. Returns `None` when there is neither or the path is empty.
+    pub fn parse(spec: &str) -> Option<FieldQuery> {
+        let at = spec.find(['=', '~'])?;
+        let path: Vec<String> = spec[..at].split('.').map(String::from).collect();
+
+        if path.iter().any(String::is_empty) {
+            return None;
+        }
+
+        Some(FieldQuery { path, exact: spec[at..].starts_with('='), value: spec[at + 1..].to_string() })
+    }
+
+    fn lookup<'a>(&self, record: &'a Value) -> Option<&'a Value> {
+        self.path.iter().try_fold(record, |value, key| match value {
+            Value::Object(object) => object.get(key),
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => None,
+        })
+    }
+}
+
+/// Matches `line` as a JSON object against every query, each paired with
+/// its value compiled. Returns `None` when the line isn't a JSON object,
+/// and no hits when any query fails.
+///
+/// Hits point at the field values in `line`, or at the hit inside a
+/// string value for `~`, so they can be highlighted. A value that can't be
+/// found as written (say it uses `\u` escapes) gets an empty hit instead.
+pub(crate) fn find(line: &str, queries: &[(FieldQuery, Pattern)]) -> Option<Vec<(Range<usize>, usize)>> {
+    if !line.trim_start().starts_with('{') {
+        return None;
+    }
+    let record: Value = serde_json::from_str(line).ok()?;
+
+    let mut hits = Vec::new();
+
+    for (index, (query, pattern)) in queries.iter().enumerate() {
+        let Some(value) = query.lookup(&record) else {
+            return Some(Vec::new());
+        };
+
+        let text = match value {
+            Value::String(text) => text.clone(),
+            value => value.to_string(),
+        };
+
+        let mut spans = pattern.find_iter(&text);
+        if query.exact {
+            spans.retain(|span| *span == (0..text.len()));
+        }
+        if spans.is_empty() {
+            return Some(Vec::new());
+        }
+
+        let key = query.path.last().map_or("", String::as_str);
+        let located = locate(line, key, value);
+
+        match located {
+            // The string is written out as is, so hits inside it line up.
+            Some(range) if !query.exact && value.is_string() && line[range.clone()] == text => {
+                hits.extend(spans.into_iter().map(|span| (range.start + span.start..range.start + span.end, index)));
+            }
+            Some(range) => hits.push((range, index)),
+            None => hits.push((0..0, index)),
+        }
+    }
+
+    // Several queries may point at the same value.
+    hits.sort_by_key(|(span, index)| (span.start, *index));
+    let mut end = 0;
+    hits.retain(|(span, _)| {
+        let keep = span.start >= end || span.is_empty();
+        end = end.max(span.end);
+        keep
+    });
+
+    Some(hits)
+}
+
+/// Finds where `key` is set to `value` in `line`, without the quotes of a
+/// string value.
+fn locate(line: &str, key: &str, value: &Value) -> Option<Range<usize>> {
+    let key = serde_json::to_string(key).ok()?;
+    let encoded = serde_json::to_string(value).ok()?;
+
+    for (at, _) in line.match_indices(&key) {
+        let Some(rest) = line[at + key.len()..].trim_start().strip_prefix(':') else {
+            continue;
+        };
+        let rest = rest.trim_start();
+
+        if rest.starts_with(&encoded) {
+            let start = line.len() - rest.len();
+            let quote = usize::from(value.is_string());
+
+            return Some(start + quote..start + encoded.len() - quote);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Boundary;
+
+    fn queries(specs: &[&str], case_sensitive: bool) -> Vec<(FieldQuery, Pattern)> {
+        specs
+            .iter()
+            .map(|spec| {
+                let query = FieldQuery::parse(spec).unwrap();
+                let pattern = Pattern::literal(std::slice::from_ref(&query.value), case_sensitive, Boundary::Substring).unwrap();
+                (query, pattern)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_specs() {
+        let query = FieldQuery::parse("req.user~a=b").unwrap();
+        assert_eq!((vec!["req".to_string(), "user".to_string()], false, "a=b"), (query.path, query.exact, query.value.as_str()));
+        assert!(FieldQuery::parse("level=").unwrap().exact);
+        assert_eq!(None, FieldQuery::parse("level"));
+        assert_eq!(None, FieldQuery::parse("req..user=x"));
+        assert_eq!(None, FieldQuery::parse("=x"));
+    }
+
+    #[test]
+    fn matches_fields_of_json_objects() {
+        let line = r#"{"level": "error", "msg": "error in handler", "req": {"user": "alice", "tags": ["a", 7]}}"#;
+
+        let hits = find(line, &queries(&["level=error"], true)).unwrap();
+        assert_eq!(vec!["error"], hits.iter().map(|(span, _)| &line[span.clone()]).collect::<Vec<_>>());
+        assert_eq!(11, hits[0].0.start);
+
+        let hits = find(line, &queries(&["req.user~lic", "req.tags.1=7"], true)).unwrap();
+        // Array items have no key to find them by in the line.
+        assert_eq!(vec![("", 1), ("lic", 0)], hits.iter().map(|(span, id)| (&line[span.clone()], *id)).collect::<Vec<_>>());
+
+        assert_eq!(Some(Vec::new()), find(line, &queries(&["level=err"], true)));
+        assert_eq!(Some(Vec::new()), find(line, &queries(&["level=error", "req.user=bob"], true)));
+        assert_eq!(Some(Vec::new()), find(line, &queries(&["req.missing~a"], true)));
+        assert_eq!(1, find(line, &queries(&["level=ERROR"], false)).unwrap().len());
+    }
+
+    #[test]
+    fn other_lines_are_not_records() {
+        assert_eq!(None, find("level=error", &queries(&["level=error"], true)));
+        assert_eq!(None, find("{not json", &queries(&["level=error"], true)));
+        assert_eq!(None, find(r#"["level", "error"]"#, &queries(&["level=error"], true)));
+    }
+}
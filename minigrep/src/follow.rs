@@ -0,0 +1,253 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long to wait at the end of the file before looking again when no
+/// change notification arrives. This is all there is to go on when the
+/// file system can't be watched.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reads a file like `tail -f` for `--follow`: at the end of the file, reads
+/// block until more is appended instead of returning nothing.
+///
+/// The file is reopened from the start when it is truncated, or when
+/// another file takes its place, as log rotation does. Changes are noticed
+/// through the platform's file watcher (inotify on Linux), and by checking
+/// every [`POLL_INTERVAL`] when no event arrives.
+pub(crate) struct Follower {
+    path: PathBuf,
+    file: File,
+    position: u64,
+    /// Kept alive for as long as its events are wanted; `None` when the
+    /// directory couldn't be watched and only polling is left.
+    watcher: Option<(RecommendedWatcher, Receiver<notify::Result<notify::Event>>)>,
+}
+
+impl Follower {
+    pub(crate) fn open(path: &Path) -> io::Result<Follower> {
+        let file = File::open(path)?;
+
+        Ok(Follower { path: path.to_path_buf(), file, position: 0, watcher: watch(path) })
+    }
+
+    /// Reopens or rewinds the file when it was rotated, reporting on stderr
+    /// as `tail` does. Returns whether there may be new data to read.
+    fn check_rotation(&mut self) -> io::Result<bool> {
+        // Mid-rotation the name may briefly point nowhere.
+        let Ok(metadata) = fs::metadata(&self.path) else {
+            return Ok(false);
+        };
+
+        if is_replaced(&self.file.metadata()?, &metadata) {
+            eprintln!("minigrep: {} has been replaced; following the new file", self.path.display());
+            self.file = File::open(&self.path)?;
+            self.position = 0;
+
+            return Ok(true);
+        }
+
+        if metadata.len() < self.position {
+            eprintln!("minigrep: {}: file truncated", self.path.display());
+            self.file.seek(SeekFrom::Start(0))?;
+            self.position = 0;
+
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    fn wait(&mut self) {
+        match &self.watcher {
+            Some((_, events)) => {
+                // Any event is reason enough to look again; drain the rest
+                // so a burst of writes doesn't wake us once per write.
+                if events.recv_timeout(POLL_INTERVAL).is_ok() {
+                    while events.try_recv().is_ok() {}
+                }
+            }
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+impl Read for Follower {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.file.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                self.position += read as u64;
+                return Ok(read);
+            }
+
+            // Everything written to the old file has been read by now, so
+            // switching to the new one loses nothing.
+            if !self.check_rotation()? {
+                self.wait();
+            }
+        }
+    }
+}
+
+/// Watches the directory holding `path`, which also sees the file being
+/// removed, renamed or created again.
+fn watch(path: &Path) -> Option<(RecommendedWatcher, Receiver<notify::Result<notify::Event>>)> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).ok()?;
+    watcher.watch(dir, RecursiveMode::NonRecursive).ok()?;
+
+    Some((watcher, events))
+}
+
+#[cfg(unix)]
+fn is_replaced(open: &fs::Metadata, current: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    (open.dev(), open.ino()) != (current.dev(), current.ino())
+}
+
+/// Without inode numbers a replacement can't be told apart from the same
+/// file; a shorter one is still caught as a truncation.
+#[cfg(not(unix))]
+fn is_replaced(_open: &fs::Metadata, _current: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::config;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        sync::mpsc::RecvTimeoutError,
+    };
+
+    use tempfile::TempDir;
+
+    use crate::{search_file, search_reader};
+
+    /// How long a test waits for the follower to see a change.
+    const DEADLINE: Duration = Duration::from_secs(10);
+
+    fn log_file(contents: &str) -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    /// Starts `read` on another thread, since it blocks until the follower
+    /// sees the change the test makes next.
+    fn reading<T: Send + 'static>(read: impl FnOnce() -> T + Send + 'static) -> Receiver<T> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(read()));
+        receiver
+    }
+
+    /// The result of a [`reading`] thread, failing the test when it takes
+    /// longer than [`DEADLINE`].
+    fn finish<T>(receiver: Receiver<T>, what: &str) -> T {
+        match receiver.recv_timeout(DEADLINE) {
+            Ok(value) => value,
+            Err(RecvTimeoutError::Timeout) => panic!("{} wasn't noticed within {:?}", what, DEADLINE),
+            Err(RecvTimeoutError::Disconnected) => panic!("reading failed before {} was noticed", what),
+        }
+    }
+
+    fn next_line(reader: &mut impl BufRead) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line
+    }
+
+    #[test]
+    fn appended_lines_are_read() {
+        let (_dir, path) = log_file("one\n");
+        let mut reader = BufReader::new(Follower::open(&path).unwrap());
+        assert_eq!("one\n", next_line(&mut reader));
+
+        let line = reading(move || next_line(&mut reader));
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"tw").unwrap();
+        file.write_all(b"o\n").unwrap();
+
+        assert_eq!("two\n", finish(line, "the appended line"));
+    }
+
+    #[test]
+    fn truncation_starts_over() {
+        let (_dir, path) = log_file("a long first line\n");
+        let mut reader = BufReader::new(Follower::open(&path).unwrap());
+        next_line(&mut reader);
+
+        let line = reading(move || next_line(&mut reader));
+        fs::write(&path, "new\n").unwrap();
+
+        assert_eq!("new\n", finish(line, "the truncation"));
+    }
+
+    #[test]
+    fn replaced_file_is_reopened() {
+        let (_dir, path) = log_file("old\n");
+        let mut reader = BufReader::new(Follower::open(&path).unwrap());
+        next_line(&mut reader);
+
+        let line = reading(move || next_line(&mut reader));
+        let rotated = path.with_extension("log.1");
+        fs::rename(&path, &rotated).unwrap();
+        fs::write(&path, "fresh\n").unwrap();
+        fs::remove_file(rotated).unwrap();
+
+        assert_eq!("fresh\n", finish(line, "the new file"));
+    }
+
+    #[test]
+    fn search_stops_at_max_count() {
+        let (_dir, path) = log_file("ERROR one\nok\n");
+        let config = config(&["minigrep", "--follow", "-m", "2", "ERROR", "f"]);
+        let follower = Follower::open(&path).unwrap();
+
+        let out = reading(move || {
+            let mut out = Vec::new();
+            search_reader(&config, None, BufReader::new(follower), &mut out).unwrap();
+            out
+        });
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"fine\nERROR two\n").unwrap();
+
+        assert_eq!("1:1:ERROR one\n4:1:ERROR two\n", String::from_utf8(finish(out, "the second match")).unwrap());
+    }
+
+    #[test]
+    fn followed_files_are_decoded() {
+        let (_dir, path) = log_file("");
+        fs::write(&path, b"caf\xe9 cr\xe8me ERROR one\n").unwrap();
+        let config = config(&["minigrep", "--follow", "-m", "2", "ERROR", "f"]);
+        let followed = path.clone();
+
+        let out = reading(move || {
+            let mut out = Vec::new();
+            search_file(&config, &followed, None, &mut out).unwrap();
+            out
+        });
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"br\xfbl\xe9e ERROR two\n").unwrap();
+
+        assert_eq!(
+            "1:14:café crème ERROR one\n2:10:brûlée ERROR two\n",
+            String::from_utf8(finish(out, "the second match")).unwrap()
+        );
+    }
+}
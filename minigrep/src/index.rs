@@ -0,0 +1,441 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    fs::{self, File},
+    io::{self, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use regex_syntax::hir::literal::Extractor;
+use tempfile::NamedTempFile;
+
+use crate::{decompress, encoding, matchers::fold_case, walk, Config};
+
+/// Name of the index file `minigrep index DIR` writes inside `DIR`.
+pub(crate) const FILE_NAME: &str = ".minigrep-index";
+
+const MAGIC: &[u8] = b"MGIDX\0";
+const VERSION: u32 = 1;
+
+/// A trigram index of the files below one directory.
+///
+/// Trigrams are taken from the case-folded text of each file, after
+/// decompression, so one index serves case-sensitive and case-insensitive
+/// searches alike. Trigrams spanning a line break are left out since a
+/// line never matches across one.
+#[derive(Debug, Default, PartialEq)]
+struct Index {
+    files: Vec<IndexedFile>,
+    /// For each trigram, the sorted indices into `files` containing it.
+    postings: BTreeMap<u32, Vec<u32>>,
+}
+
+#[derive(Debug, PartialEq)]
+struct IndexedFile {
+    /// Relative to the indexed directory, with `/` separators.
+    path: String,
+    /// Modification time in nanoseconds since the epoch and size, compared
+    /// to tell whether the file changed since it was indexed.
+    modified: u64,
+    size: u64,
+}
+
+/// What `minigrep index` did to one directory.
+#[derive(Debug, PartialEq)]
+pub(crate) struct IndexReport {
+    pub files: usize,
+    /// Files read because they are new or changed since the last run.
+    pub updated: usize,
+    pub removed: usize,
+}
+
+/// Builds or refreshes the index of `root`. Files whose modification time
+/// and size are unchanged since the previous index keep their trigrams
+/// without being read again.
+pub(crate) fn update(root: &Path, config: &Config) -> Result<IndexReport, Box<dyn Error>> {
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", root.display()).into());
+    }
+
+    // A missing or unreadable index just means starting over.
+    let old = Index::load(&root.join(FILE_NAME)).unwrap_or_default();
+    let old_trigrams = old.trigrams_by_file();
+    let old_files: HashMap<&str, usize> = old.files.iter().enumerate().map(|(id, file)| (file.path.as_str(), id)).collect();
+
+    let mut index = Index::default();
+    let mut report = IndexReport { files: 0, updated: 0, removed: 0 };
+    let mut still_there = 0;
+
+    for path in walk::files(root, config)? {
+        let Some(relative) = relative_path(root, &path) else {
+            continue;
+        };
+        let Ok((modified, size)) = stamp(&path) else {
+            continue;
+        };
+
+        let previous = old_files.get(relative.as_str()).copied();
+        still_there += usize::from(previous.is_some());
+
+        let trigrams = match previous {
+            Some(id) if old.files[id].modified == modified && old.files[id].size == size => old_trigrams[id].clone(),
+            _ => {
+                // Files search would skip, like binary ones, stay out of the
+                // index and so are always searched.
+                let Ok(trigrams) = file_trigrams(&path, config) else {
+                    continue;
+                };
+                report.updated += 1;
+                trigrams
+            }
+        };
+
+        let id = index.files.len() as u32;
+        for trigram in trigrams {
+            index.postings.entry(trigram).or_default().push(id);
+        }
+        index.files.push(IndexedFile { path: relative, modified, size });
+    }
+
+    report.files = index.files.len();
+    report.removed = old.files.len() - still_there;
+    index.save(&root.join(FILE_NAME))?;
+
+    Ok(report)
+}
+
+/// Drops the files in `files`, found below `root`, that the index of `root`
+/// shows can't match `config`. Files that aren't in the index or changed
+/// since it was built are always kept, so a stale index only costs speed.
+pub(crate) fn narrow(root: &Path, config: &Config, files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let Some(required) = required_trigrams(config) else {
+        return files;
+    };
+
+    let index = match Index::load(&root.join(FILE_NAME)) {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("Not using the index of {}: {}", root.display(), e);
+            return files;
+        }
+    };
+
+    let candidates = index.candidates(&required);
+    let ids: HashMap<&str, usize> = index.files.iter().enumerate().map(|(id, file)| (file.path.as_str(), id)).collect();
+
+    files
+        .into_iter()
+        .filter(|path| {
+            let indexed = relative_path(root, path).and_then(|relative| ids.get(relative.as_str()).copied());
+
+            match indexed {
+                Some(id) => {
+                    let file = &index.files[id];
+                    candidates.contains(&id) || stamp(path).map_or(true, |stamp| stamp != (file.modified, file.size))
+                }
+                None => true,
+            }
+        })
+        .collect()
+}
+
+/// The trigrams a file must contain to possibly match `config`, as a list
+/// of alternatives: a file can match when it has every trigram of at least
+/// one of them. `None` when the index can't help.
+fn required_trigrams(config: &Config) -> Option<Vec<Vec<u32>>> {
+    // -v and -c report on files without hits too, --fuzzy hits needn't
+    // share any trigram with the query, and --field values may be escaped
+    // or written as numbers in the JSON. -z decompresses files the index
+    // read as they are.
+    if config.invert_match || config.count || config.fuzzy.is_some() || !config.fields.is_empty() || config.search_zip {
+        return None;
+    }
+
+    let mut alternatives = Vec::new();
+
+    for pattern in &config.patterns {
+        if config.use_regex {
+            // Parsed case-sensitively: the literals are folded just like
+            // the indexed text, which covers case-insensitive searches.
+            let hir = regex_syntax::parse(pattern).ok()?;
+            let literals = Extractor::new().extract(&hir);
+
+            for literal in literals.literals()? {
+                alternatives.push(trigrams(valid_prefix(literal.as_bytes())));
+            }
+        } else {
+            alternatives.push(trigrams(pattern));
+        }
+    }
+
+    Some(alternatives)
+}
+
+/// Extracted literals may be cut short partway through a character.
+fn valid_prefix(bytes: &[u8]) -> &str {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default(),
+    }
+}
+
+/// The distinct trigrams of the case-folded `text`, sorted.
+fn trigrams(text: &str) -> Vec<u32> {
+    let folded: String = text.chars().flat_map(fold_case).collect();
+
+    let trigrams: BTreeSet<u32> = folded
+        .as_bytes()
+        .windows(3)
+        .filter(|window| !window.contains(&b'\n'))
+        .map(|window| u32::from(window[0]) << 16 | u32::from(window[1]) << 8 | u32::from(window[2]))
+        .collect();
+
+    trigrams.into_iter().collect()
+}
+
+/// Reads `path` the way a search would, so the trigrams match what it sees.
+fn file_trigrams(path: &Path, config: &Config) -> Result<Vec<u32>, Box<dyn Error>> {
+    let reader = decompress::decompress(Box::new(BufReader::new(File::open(path)?)), Some(path), false)?;
+    let (mut reader, binary) = encoding::decode(reader, config.encoding)?;
+    if binary {
+        return Err("binary file".into());
+    }
+
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+
+    Ok(trigrams(&contents))
+}
+
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts: Option<Vec<&str>> = relative.iter().map(|part| part.to_str()).collect();
+
+    Some(parts?.join("/"))
+}
+
+fn stamp(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos() as u64);
+
+    Ok((modified, metadata.len()))
+}
+
+impl Index {
+    /// Indices into `files` that contain every trigram of some alternative.
+    fn candidates(&self, alternatives: &[Vec<u32>]) -> BTreeSet<usize> {
+        let mut candidates = BTreeSet::new();
+
+        for trigrams in alternatives {
+            let mut matching: Option<BTreeSet<u32>> = None;
+
+            for trigram in trigrams {
+                let files: BTreeSet<u32> = self.postings.get(trigram).into_iter().flatten().copied().collect();
+                matching = Some(match matching {
+                    Some(matching) => matching.intersection(&files).copied().collect(),
+                    None => files,
+                });
+            }
+
+            match matching {
+                Some(matching) => candidates.extend(matching.into_iter().map(|id| id as usize)),
+                // Shorter than a trigram: anything could match.
+                None => return (0..self.files.len()).collect(),
+            }
+        }
+
+        candidates
+    }
+
+    /// The trigrams of each file, the other way around from `postings`.
+    fn trigrams_by_file(&self) -> Vec<Vec<u32>> {
+        let mut trigrams = vec![Vec::new(); self.files.len()];
+
+        for (&trigram, files) in &self.postings {
+            for &id in files {
+                trigrams[id as usize].push(trigram);
+            }
+        }
+
+        trigrams
+    }
+
+    /// Reads an index written by [`Index::save`].
+    fn load(path: &Path) -> io::Result<Index> {
+        let bytes = fs::read(path)?;
+        let mut reader = Reader { bytes: &bytes };
+
+        if reader.take(MAGIC.len())? != MAGIC || reader.u32()? != VERSION {
+            return Err(corrupt());
+        }
+
+        let mut index = Index::default();
+
+        for _ in 0..reader.u32()? {
+            let length = reader.u32()? as usize;
+            let path = String::from_utf8(reader.take(length)?.to_vec()).map_err(|_| corrupt())?;
+            index.files.push(IndexedFile { path, modified: reader.u64()?, size: reader.u64()? });
+        }
+
+        for _ in 0..reader.u32()? {
+            let trigram = reader.u32()?;
+            let files = (0..reader.u32()?).map(|_| reader.u32()).collect::<io::Result<Vec<u32>>>()?;
+
+            if files.iter().any(|&id| id as usize >= index.files.len()) {
+                return Err(corrupt());
+            }
+            index.postings.insert(trigram, files);
+        }
+
+        Ok(index)
+    }
+
+    /// Writes the index next to its final `path` and renames it into place,
+    /// so a search never reads a half-written index. Numbers are stored
+    /// little-endian.
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+
+        bytes.extend((self.files.len() as u32).to_le_bytes());
+        for file in &self.files {
+            bytes.extend((file.path.len() as u32).to_le_bytes());
+            bytes.extend(file.path.as_bytes());
+            bytes.extend(file.modified.to_le_bytes());
+            bytes.extend(file.size.to_le_bytes());
+        }
+
+        bytes.extend((self.postings.len() as u32).to_le_bytes());
+        for (trigram, files) in &self.postings {
+            bytes.extend(trigram.to_le_bytes());
+            bytes.extend((files.len() as u32).to_le_bytes());
+            for id in files {
+                bytes.extend(id.to_le_bytes());
+            }
+        }
+
+        let mut temp = NamedTempFile::new_in(path.parent().unwrap_or(Path::new(".")))?;
+        temp.write_all(&bytes)?;
+        temp.persist(path)?;
+
+        Ok(())
+    }
+}
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupt or outdated index, run `minigrep index` again")
+}
+
+/// Reads the fields of a saved index in order.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < length {
+            return Err(corrupt());
+        }
+
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::config;
+    use crate::Command;
+    use tempfile::TempDir;
+
+    /// `minigrep index ROOT`.
+    fn index_config(root: &Path) -> Config {
+        let config = config(&["minigrep", "index", root.to_str().unwrap()]);
+        assert_eq!(Command::Index, config.command);
+        config
+    }
+
+    fn corpus() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("guides")).unwrap();
+        fs::write(root.join("install.md"), "Run cargo install minigrep\n").unwrap();
+        fs::write(root.join("guides/errors.md"), "An ERROR 500 means the server failed\n").unwrap();
+        fs::write(root.join("guides/german.md"), "Die Straße ist lang\n").unwrap();
+        dir
+    }
+
+    fn narrowed(root: &Path, args: &[&str]) -> Vec<PathBuf> {
+        let config = config(args);
+        narrow(root, &config, walk::files(root, &config).unwrap())
+    }
+
+    #[test]
+    fn index_round_trips() {
+        let dir = corpus();
+        let root = dir.path();
+        update(root, &index_config(root)).unwrap();
+        let index = Index::load(&root.join(FILE_NAME)).unwrap();
+
+        let path = root.join("copy");
+        index.save(&path).unwrap();
+        let copy = Index::load(&path).unwrap();
+
+        assert_eq!(index, copy);
+        assert_eq!(vec!["guides/errors.md", "guides/german.md", "install.md"], copy.files.iter().map(|file| &file.path).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn narrows_literal_and_regex_searches() {
+        let dir = corpus();
+        let root = dir.path();
+        update(root, &index_config(root)).unwrap();
+
+        let server = narrowed(root, &["minigrep", "--index", "server", "d"]);
+        let either = narrowed(root, &["minigrep", "--index", "-e", "server", "-e", "cargo", "d"]);
+        let regex = narrowed(root, &["minigrep", "--index", "--regex", r"ERROR \d+", "d"]);
+        let folded = narrowed(root, &["minigrep", "--index", "-i", "STRASSE", "d"]);
+        let short = narrowed(root, &["minigrep", "--index", "Ru", "d"]);
+        let nowhere = narrowed(root, &["minigrep", "--index", "nowhere", "d"]);
+
+        assert_eq!(vec![root.join("guides/errors.md")], server);
+        assert_eq!(vec![root.join("guides/errors.md"), root.join("install.md")], either);
+        assert_eq!(vec![root.join("guides/errors.md")], regex);
+        assert_eq!(vec![root.join("guides/german.md")], folded);
+        assert_eq!(3, short.len());
+        assert!(nowhere.is_empty());
+    }
+
+    #[test]
+    fn changed_and_new_files_are_reindexed_or_kept() {
+        let dir = corpus();
+        let root = dir.path();
+        let index_config = index_config(root);
+        assert_eq!(IndexReport { files: 3, updated: 3, removed: 0 }, update(root, &index_config).unwrap());
+
+        // Not yet reindexed: both must still be searched.
+        fs::write(root.join("install.md"), "Run cargo build, then find the server\n").unwrap();
+        fs::write(root.join("new.md"), "a new server\n").unwrap();
+        fs::remove_file(root.join("guides/german.md")).unwrap();
+        let stale = narrowed(root, &["minigrep", "--index", "server", "d"]);
+
+        let report = update(root, &index_config).unwrap();
+        let fresh = narrowed(root, &["minigrep", "--index", "cargo build", "d"]);
+
+        assert_eq!(vec![root.join("guides/errors.md"), root.join("install.md"), root.join("new.md")], stale);
+        assert_eq!(IndexReport { files: 3, updated: 2, removed: 1 }, report);
+        assert_eq!(vec![root.join("install.md")], fresh);
+    }
+}
@@ -0,0 +1,389 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
+    terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{decompress, encoding, find_matches, Config, Match};
+
+/// Rows taken by the prompt and the status line above the results.
+const HEADER_ROWS: usize = 2;
+
+/// How long typing has to pause before the query is searched again, so a
+/// burst of keys or a paste costs one search instead of one per key.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Runs `--interactive` on the single file in `config.file_names`.
+///
+/// The screen is drawn on stderr, leaving stdout for the line picked with
+/// Enter, so `$(minigrep --interactive notes.txt)` works as expected.
+pub(crate) fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(&config.file_names[0]);
+    let reader = decompress::decompress(Box::new(BufReader::new(File::open(path)?)), Some(path), config.search_zip)?;
+    let (mut reader, binary) = encoding::decode(reader, config.encoding)?;
+    if binary {
+        return Err("binary file".into());
+    }
+
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+
+    let mut state = State::new(config, &contents);
+    let mut screen = io::stderr();
+
+    let picked = {
+        let _terminal = RawTerminal::enter()?;
+
+        loop {
+            let (width, height) = terminal::size()?;
+            state.scroll_into_view(usize::from(height).saturating_sub(HEADER_ROWS));
+            draw(&mut screen, &state, usize::from(width), usize::from(height))?;
+
+            // Typed keys show up in the prompt at once, but the results
+            // only catch up once typing pauses.
+            if state.stale && !event::poll(DEBOUNCE)? {
+                state.refresh();
+                continue;
+            }
+
+            // Resizes and other events just redraw.
+            if let Event::Key(key) = event::read()? {
+                match state.handle(key) {
+                    Action::Continue => {}
+                    Action::Quit => break None,
+                    Action::Pick(index) => break Some(index),
+                }
+            }
+        }
+    };
+
+    if let Some(index) = picked {
+        println!("{}", state.matches[index].line);
+    }
+
+    Ok(())
+}
+
+/// Raw mode and the alternate screen, undone on drop so the terminal is
+/// restored even when searching fails.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<RawTerminal> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stderr(), EnterAlternateScreen)?;
+
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// What the UI should do after a key press.
+#[derive(Debug, PartialEq)]
+enum Action {
+    Continue,
+    Quit,
+    /// Print `matches[index]` and exit.
+    Pick(usize),
+}
+
+/// The query being typed and the results for it.
+struct State<'a> {
+    config: Config,
+    contents: &'a str,
+    query: String,
+    matches: Vec<Match<'a>>,
+    /// Why the current query doesn't compile, such as an unfinished regex.
+    /// The results of the last good query stay up meanwhile.
+    error: Option<String>,
+    selected: usize,
+    /// Index of the first result on screen.
+    scroll: usize,
+    /// Whether `query` changed since `matches` were found.
+    stale: bool,
+}
+
+impl<'a> State<'a> {
+    fn new(config: Config, contents: &'a str) -> State<'a> {
+        let query = config.patterns.first().cloned().unwrap_or_default();
+        let mut state = State { config, contents, query, matches: Vec::new(), error: None, selected: 0, scroll: 0, stale: true };
+        state.refresh();
+
+        state
+    }
+
+    /// Searches for the current query, if the results are for an older one.
+    fn refresh(&mut self) {
+        if !self.stale {
+            return;
+        }
+
+        match self.config.set_query(&self.query) {
+            Ok(()) => {
+                self.matches = find_matches(&self.config, self.contents);
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+
+        self.selected = 0;
+        self.scroll = 0;
+        self.stale = false;
+    }
+
+    fn handle(&mut self, key: KeyEvent) -> Action {
+        if key.kind == KeyEventKind::Release {
+            return Action::Continue;
+        }
+
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key.code {
+            KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('c') if control => return Action::Quit,
+            KeyCode::Char('u') if control => {
+                self.query.clear();
+                self.stale = true;
+            }
+            KeyCode::Char(c) if !control => {
+                self.query.push(c);
+                self.stale = true;
+            }
+            KeyCode::Backspace if !self.query.is_empty() => {
+                self.query.pop();
+                self.stale = true;
+            }
+            _ => return self.select(key.code),
+        }
+
+        Action::Continue
+    }
+
+    /// Moves the selection, or picks the selected result on Enter. Keys
+    /// typed just before count, so the results are brought up to date first.
+    fn select(&mut self, code: KeyCode) -> Action {
+        self.refresh();
+
+        match code {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(self.matches.len().saturating_sub(1)),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(10),
+            KeyCode::PageDown => self.selected = (self.selected + 10).min(self.matches.len().saturating_sub(1)),
+            KeyCode::Enter if !self.matches.is_empty() => return Action::Pick(self.selected),
+            _ => {}
+        }
+
+        Action::Continue
+    }
+
+    /// Moves `scroll` so the selected result is among the `rows` shown.
+    fn scroll_into_view(&mut self, rows: usize) {
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if rows > 0 && self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+    }
+}
+
+fn draw<W: Write>(out: &mut W, state: &State, width: usize, height: usize) -> io::Result<()> {
+    queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 1))?;
+
+    match &state.error {
+        Some(error) => queue!(out, SetForegroundColor(Color::Red), Print(truncate(error, width)), ResetColor)?,
+        None => {
+            let status = format!("{} matching lines", state.matches.len());
+            queue!(out, SetAttribute(Attribute::Dim), Print(truncate(&status, width)), SetAttribute(Attribute::Reset))?;
+        }
+    }
+
+    let rows = height.saturating_sub(HEADER_ROWS);
+    for (row, (index, found)) in state.matches.iter().enumerate().skip(state.scroll).take(rows).enumerate() {
+        queue!(out, cursor::MoveTo(0, (row + HEADER_ROWS) as u16))?;
+        draw_match(out, found, width, index == state.selected)?;
+    }
+
+    let prompt = format!("> {}", state.query);
+    let cursor_column = truncate(&prompt, width).width().min(width.saturating_sub(1));
+    queue!(out, cursor::MoveTo(0, 0), Print(truncate(&prompt, width)), cursor::MoveTo(cursor_column as u16, 0))?;
+
+    out.flush()
+}
+
+/// Draws one result as `line:text`, cut to `width`, with the hits in red.
+fn draw_match<W: Write>(out: &mut W, found: &Match, width: usize, selected: bool) -> io::Result<()> {
+    if selected {
+        queue!(out, SetAttribute(Attribute::Reverse))?;
+    }
+
+    let number = format!("{}:", found.line_number);
+    queue!(out, SetForegroundColor(Color::Green), Print(truncate(&number, width)), ResetColor)?;
+    let mut remaining = width.saturating_sub(number.width());
+
+    let mut pieces = Vec::new();
+    let mut written = 0;
+    for span in &found.spans {
+        pieces.push((&found.line[written..span.start], false));
+        pieces.push((&found.line[span.clone()], true));
+        written = span.end;
+    }
+    pieces.push((&found.line[written..], false));
+
+    for (text, hit) in pieces {
+        let text = truncate(text, remaining);
+        remaining -= text.width();
+
+        if hit {
+            queue!(out, SetForegroundColor(Color::Red), Print(text), ResetColor)?;
+        } else {
+            queue!(out, Print(text))?;
+        }
+    }
+
+    queue!(out, SetAttribute(Attribute::Reset))
+}
+
+/// The start of `text` that fits in `width` columns, with tabs and other
+/// control characters shown as spaces so they can't move the cursor. Wide
+/// characters, such as CJK, take two columns.
+fn truncate(text: &str, width: usize) -> String {
+    let mut truncated = String::new();
+    let mut used = 0;
+
+    for c in text.chars() {
+        let c = if c.is_control() { ' ' } else { c };
+        used += c.width().unwrap_or(0);
+        if used > width {
+            break;
+        }
+
+        truncated.push(c);
+    }
+
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::config;
+
+    const CONTENTS: &str = "apple pie\nbanana split\napple crumble\ncherry tart";
+
+    fn state(args: &[&str]) -> State<'static> {
+        State::new(config(args), CONTENTS)
+    }
+
+    fn press(state: &mut State, code: KeyCode) -> Action {
+        state.handle(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    /// Types `text` and pauses, so the results catch up.
+    fn type_text(state: &mut State, text: &str) {
+        for c in text.chars() {
+            press(state, KeyCode::Char(c));
+        }
+        state.refresh();
+    }
+
+    fn lines<'a>(state: &State<'a>) -> Vec<&'a str> {
+        state.matches.iter().map(|found| found.line).collect()
+    }
+
+    #[test]
+    fn typing_refines_the_results() {
+        let mut state = state(&["minigrep", "--interactive", "f"]);
+        assert_eq!(4, state.matches.len());
+
+        type_text(&mut state, "apple");
+        assert_eq!(vec!["apple pie", "apple crumble"], lines(&state));
+
+        type_text(&mut state, " c");
+        assert_eq!(vec!["apple crumble"], lines(&state));
+
+        press(&mut state, KeyCode::Backspace);
+        press(&mut state, KeyCode::Backspace);
+        state.refresh();
+        assert_eq!(2, state.matches.len());
+    }
+
+    #[test]
+    fn results_wait_for_typing_to_pause() {
+        let mut state = state(&["minigrep", "--interactive", "f"]);
+
+        press(&mut state, KeyCode::Char('c'));
+        press(&mut state, KeyCode::Char('h'));
+        assert!(state.stale);
+        assert_eq!(4, state.matches.len());
+
+        // Enter picks from the results for what was typed.
+        assert_eq!(Action::Pick(0), press(&mut state, KeyCode::Enter));
+        assert_eq!(vec!["cherry tart"], lines(&state));
+    }
+
+    #[test]
+    fn arrows_move_and_enter_picks() {
+        let mut state = state(&["minigrep", "--interactive", "-e", "a", "f"]);
+
+        press(&mut state, KeyCode::Up);
+        assert_eq!(0, state.selected);
+        for _ in 0..10 {
+            press(&mut state, KeyCode::Down);
+        }
+        assert_eq!(3, state.selected);
+
+        assert_eq!(Action::Pick(3), press(&mut state, KeyCode::Enter));
+        assert_eq!(Action::Quit, press(&mut state, KeyCode::Esc));
+    }
+
+    #[test]
+    fn unfinished_regex_keeps_the_last_results() {
+        let mut state = state(&["minigrep", "--interactive", "--regex", "f"]);
+
+        type_text(&mut state, "(apple|cherry");
+        assert!(state.error.is_some());
+
+        type_text(&mut state, ")");
+        assert_eq!(None, state.error);
+        assert_eq!(vec!["apple pie", "apple crumble", "cherry tart"], lines(&state));
+    }
+
+    #[test]
+    fn scrolling_follows_the_selection() {
+        let mut state = state(&["minigrep", "--interactive", "f"]);
+
+        state.selected = 3;
+        state.scroll_into_view(2);
+        assert_eq!(2, state.scroll);
+
+        state.selected = 0;
+        state.scroll_into_view(2);
+        assert_eq!(0, state.scroll);
+    }
+
+    #[test]
+    fn truncate_counts_columns() {
+        assert_eq!("日本", truncate("日本語", 5));
+        assert_eq!(4, truncate("日本語のテキスト", 5).width());
+        assert_eq!("a b", truncate("a\tbc", 3));
+        assert_eq!("cafe\u{301}", truncate("cafe\u{301}!", 4));
+    }
+}
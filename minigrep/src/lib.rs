@@ -1,81 +1,511 @@
 use std::{
-    fs, 
+    borrow::Cow,
+    fs::File,
     error::Error,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    ops::Range,
+    path::Path,
+    time::Instant,
 };
 
-use dotenv::dotenv;
+use fields::FieldQuery;
 
-pub struct Config {
-    pub query: String,
-    pub file_name: String,
-    pub case_sensitive: bool
+mod config;
+pub mod decompress;
+pub mod encoding;
+pub mod fields;
+mod follow;
+mod index;
+mod interactive;
+mod matchers;
+mod output;
+mod parallel;
+mod replace;
+mod searcher;
+mod settings;
+pub mod walk;
+
+pub use config::{Command, Config, ConfigError, Invocation, USAGE};
+pub use matchers::{BoundedMatcher, CaseInsensitiveMatcher, FuzzyMatcher, LiteralMatcher, RegexMatcher};
+pub use output::{ColorChoice, OutputFormat, Stats};
+pub use searcher::{Matcher, Searcher, Sink};
+
+/// How much of the surrounding text a hit has to cover.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Boundary {
+    /// Anywhere in the line, the default.
+    #[default]
+    Substring,
+    /// A whole word (`-w`): not preceded or followed by a letter, digit or
+    /// underscore, in any script.
+    Word,
+    /// The entire line (`-x`).
+    Line,
+}
+
+impl Boundary {
+    /// Whether the hit at `span` in `line` respects this boundary. With
+    /// `--multiline`, `line` holds many lines and `-x` hits must fill one.
+    fn allows(self, line: &str, span: &Range<usize>) -> bool {
+        match self {
+            Boundary::Substring => true,
+            Boundary::Word => {
+                let before = line[..span.start].chars().next_back();
+                let after = line[span.end..].chars().next();
+
+                !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+            }
+            Boundary::Line => {
+                let rest = &line[span.end..];
+                let ends_line = rest.is_empty() || rest.starts_with('\n') || rest.starts_with("\r\n");
+
+                (span.start == 0 || line[..span.start].ends_with('\n')) && ends_line
+            }
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
 }
 
-impl Config {
-    pub fn new(args: &[String]) -> Result<Config, &str> {
-        if args.len() < 3 {
-            return Err("Not enough arguments!");
+/// The compiled form of the patterns a search looks for. A line matches
+/// when any of the patterns hits, and every hit remembers which pattern
+/// (by index) produced it.
+pub struct Pattern {
+    kind: PatternKind,
+}
+
+enum PatternKind {
+    Literal(LiteralMatcher),
+    CaseInsensitive(CaseInsensitiveMatcher),
+    Bounded(BoundedMatcher),
+    Regex(RegexMatcher),
+    /// `--field` queries, each with its value compiled, for JSON-lines
+    /// input. Lines that aren't JSON objects are searched with `fallback`.
+    Fields { queries: Vec<(FieldQuery, Pattern)>, fallback: Box<Pattern> },
+    Fuzzy(FuzzyMatcher),
+}
+
+impl Pattern {
+    /// Compiles a single regular expression.
+    pub fn new(query: &str, case_sensitive: bool) -> Result<Pattern, regex::Error> {
+        Pattern::regex(&[query.to_string()], case_sensitive, Boundary::Substring)
+    }
+
+    pub fn regex(patterns: &[String], case_sensitive: bool, boundary: Boundary) -> Result<Pattern, regex::Error> {
+        Ok(Pattern { kind: PatternKind::Regex(RegexMatcher::new(patterns, case_sensitive, boundary)?) })
+    }
+
+    pub fn literal(patterns: &[String], case_sensitive: bool, boundary: Boundary) -> Result<Pattern, aho_corasick::BuildError> {
+        if boundary != Boundary::Substring {
+            return Ok(Pattern { kind: PatternKind::Bounded(BoundedMatcher::new(patterns, case_sensitive, boundary)?) });
+        }
+
+        if case_sensitive {
+            return Ok(Pattern { kind: PatternKind::Literal(LiteralMatcher::new(patterns)?) });
         }
 
-        let query = args[1].clone();
-        let file_name = args[2].clone();
-        
-        dotenv().ok();
+        Ok(Pattern { kind: PatternKind::CaseInsensitive(CaseInsensitiveMatcher::new(patterns)?) })
+    }
+
+    pub fn fuzzy(patterns: &[String], max_distance: usize, case_sensitive: bool) -> Pattern {
+        Pattern { kind: PatternKind::Fuzzy(FuzzyMatcher::new(patterns, max_distance, case_sensitive)) }
+    }
+
+    /// Selects JSON records whose fields satisfy every query, and other
+    /// lines that `fallback` matches.
+    pub fn fields(queries: Vec<(FieldQuery, Pattern)>, fallback: Pattern) -> Pattern {
+        Pattern { kind: PatternKind::Fields { queries, fallback: Box::new(fallback) } }
+    }
+
+    /// Returns the byte range of the first match in `line`.
+    pub fn find(&self, line: &str) -> Option<Range<usize>> {
+        self.find_all(line).into_iter().next().map(|(span, _)| span)
+    }
 
-        let env_key = std::env::var("CASE_SENSITIVE").expect("CASE_SENSITIVE must be set.");
-        
-        let case_sensitive = if env_key == "true" { true } else { false };
-    
-        Ok(Config { query, file_name, case_sensitive })
+    /// Returns the byte ranges of every non-overlapping match in `line`.
+    pub fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        self.find_all(line).into_iter().map(|(span, _)| span).collect()
+    }
+
+    /// Replaces every match in `line` with `replacement`. For regular
+    /// expressions `$1` or `${name}` in `replacement` expand to capture
+    /// groups; for literals it is inserted as is.
+    pub fn replace_all<'a>(&self, line: &'a str, replacement: &str) -> Cow<'a, str> {
+        match &self.kind {
+            PatternKind::Regex(matcher) => matcher.replace_all(line, replacement),
+            PatternKind::Literal(_)
+            | PatternKind::CaseInsensitive(_)
+            | PatternKind::Bounded(_)
+            | PatternKind::Fields { .. }
+            | PatternKind::Fuzzy(_) => {
+                let spans = self.find_iter(line);
+                if spans.is_empty() {
+                    return Cow::Borrowed(line);
+                }
+
+                let mut replaced = String::with_capacity(line.len());
+                let mut written = 0;
+
+                for span in spans {
+                    replaced.push_str(&line[written..span.start]);
+                    replaced.push_str(replacement);
+                    written = span.end;
+                }
+                replaced.push_str(&line[written..]);
+
+                Cow::Owned(replaced)
+            }
+        }
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>>{
-    let contents = fs::read_to_string(config.file_name)?;
+impl Matcher for Pattern {
+    fn find_all(&self, line: &str) -> Vec<(Range<usize>, usize)> {
+        match &self.kind {
+            PatternKind::Literal(matcher) => matcher.find_all(line),
+            PatternKind::CaseInsensitive(matcher) => matcher.find_all(line),
+            PatternKind::Bounded(matcher) => matcher.find_all(line),
+            PatternKind::Regex(matcher) => matcher.find_all(line),
+            PatternKind::Fields { queries, fallback } => fields::find(line, queries).unwrap_or_else(|| fallback.find_all(line)),
+            PatternKind::Fuzzy(matcher) => matcher.find_all(line),
+        }
+    }
 
-    let results: Vec<&str> = if config.case_sensitive {
-        search(&config.query, &contents)
-    } else {
-        search_case_insensitive(&config.query, &contents)
-    };
+    fn is_match(&self, line: &str) -> bool {
+        match &self.kind {
+            PatternKind::Literal(matcher) => matcher.is_match(line),
+            PatternKind::CaseInsensitive(matcher) => matcher.is_match(line),
+            PatternKind::Regex(matcher) => matcher.is_match(line),
+            PatternKind::Bounded(_) | PatternKind::Fields { .. } | PatternKind::Fuzzy(_) => !self.find_all(line).is_empty(),
+        }
+    }
+
+    /// Edit distance of the closest hit in `line` for `--fuzzy` patterns;
+    /// `None` when nothing is close enough or the pattern isn't fuzzy.
+    fn distance(&self, line: &str) -> Option<usize> {
+        match &self.kind {
+            PatternKind::Fuzzy(matcher) => matcher.distance(line),
+            _ => None,
+        }
+    }
+}
+
+/// A matching line together with where it was found. With `--multiline`
+/// a match can cover several lines, which `line` then holds in full.
+#[derive(Debug, PartialEq)]
+pub struct Match<'a> {
+    /// 1-based line number.
+    pub line_number: usize,
+    /// 1-based number of the last line covered; `line_number` unless the
+    /// match spans lines.
+    pub end_line_number: usize,
+    /// Byte offset of the start of `line` within the searched contents.
+    pub byte_offset: usize,
+    pub line: &'a str,
+    /// Byte ranges of every hit within `line`, in order. Empty for lines
+    /// selected by `-v`.
+    pub spans: Vec<Range<usize>>,
+    /// For each entry in `spans`, the index into [`Config::patterns`] of the
+    /// pattern that hit there.
+    pub pattern_ids: Vec<usize>,
+    /// Edit distance between the query and the hit with `--fuzzy`.
+    pub distance: Option<usize>,
+}
+
+impl Match<'_> {
+    /// 1-based byte column of the first hit, as editors expect it.
+    pub fn column(&self) -> usize {
+        self.spans.first().map_or(1, |span| span.start + 1)
+    }
+
+    /// Text of the first hit.
+    pub fn matched_text(&self) -> &str {
+        self.spans.first().map_or("", |span| &self.line[span.clone()])
+    }
+}
+
+/// Does what `config` asks for, printing to stdout. Matches are colored
+/// with `--color auto` when stdout is a terminal and `NO_COLOR` isn't set.
+pub fn run(mut config: Config) -> Result<(), Box<dyn Error>>{
+    let stdout = io::stdout();
+
+    if config.color == ColorChoice::Auto {
+        let colored = stdout.is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        config.color = if colored { ColorChoice::Always } else { ColorChoice::Never };
+    }
+
+    run_to(config, &mut stdout.lock())
+}
+
+/// Like [`run`], writing to `out` instead, without color unless
+/// `--color always` was given. `--interactive` still takes over the
+/// terminal.
+pub fn run_to<W: Write>(config: Config, out: &mut W) -> Result<(), Box<dyn Error>> {
+    if config.interactive {
+        return interactive::run(config);
+    }
 
-    println!("Result: ");
+    let started = Instant::now();
 
-    for line in results {
-        println!("{}", line);
+    if config.command == Command::Index {
+        for name in &config.file_names {
+            let report = index::update(Path::new(name), &config)?;
+            writeln!(
+                out,
+                "Indexed {} files in {} ({} new or changed, {} removed)",
+                report.files, name, report.updated, report.removed
+            )?;
+        }
+
+        return Ok(());
+    }
+
+    // A lone file keeps the plain, unlabelled output, unless the name is
+    // what's being asked for. Its errors still say which file they are
+    // about.
+    if let [name] = config.file_names.as_slice() {
+        if !Path::new(name).is_dir() {
+            let named = |e: Box<dyn Error>| -> Box<dyn Error> { format!("{}: {}", name, e).into() };
+
+            let stats = if config.output == OutputFormat::Text && !config.files_with_matches {
+                writeln!(out, "Result: ")?;
+                search_file(&config, Path::new(name), None, out).map_err(named)?
+            } else {
+                search_file(&config, Path::new(name), Some(name), out).map_err(named)?
+            };
+
+            output::write_summary(out, &config, &stats, started.elapsed())?;
+
+            return Ok(());
+        }
     }
 
+    let mut files = Vec::new();
+
+    for name in &config.file_names {
+        let path = Path::new(name);
+
+        if path.is_dir() && config.use_index {
+            files.extend(index::narrow(path, &config, walk::files(path, &config)?));
+        } else if path.is_dir() {
+            files.extend(walk::files(path, &config)?);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    let stats = parallel::search_files(&config, &files, out)?;
+
+    output::write_summary(out, &config, &stats, started.elapsed())?;
+
     Ok(())
 }
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+/// Runs the search selected by `config` over `contents`, keeping the
+/// position of every matching line.
+pub fn find_matches<'a>(config: &Config, contents: &'a str) -> Vec<Match<'a>> {
+    if config.multiline {
+        return searcher::find_multiline(&config.pattern, contents);
+    }
+
     let mut results = Vec::new();
 
-    for line in contents.lines() {
-        if line.contains(query) {
-            results.push(line);
+    for (index, (byte_offset, line)) in lines_with_offsets(contents).enumerate() {
+        let (spans, pattern_ids): (Vec<_>, Vec<_>) = config.pattern.find_all(line).into_iter().unzip();
+
+        if !spans.is_empty() {
+            let distance = config.pattern.distance(line);
+            results.push(Match { line_number: index + 1, end_line_number: index + 1, byte_offset, line, spans, pattern_ids, distance });
         }
     }
 
     results
 }
 
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    let mut results = Vec::new();
+/// Searches `reader` with a [`Searcher`] set up from `config` and writes
+/// the results to `out` in `config.output` format. Text output looks like
+/// grep's: `line:column:text` for hits, `line-text` for context lines and
+/// `--` between groups that aren't adjacent, with `label` prepended when
+/// searching many files. `-c` and `-l` replace the lines with a count or
+/// the label.
+pub fn search_reader<R: BufRead, W: Write>(
+    config: &Config,
+    label: Option<&str>,
+    reader: R,
+    out: &mut W,
+) -> Result<Stats, Box<dyn Error>> {
+    let mut searcher = Searcher::from(config);
+
+    if !config.count && !config.files_with_matches {
+        let mut printer = output::Printer { out, config, label };
+        return Ok(searcher.search_reader(&config.pattern, reader, &mut printer)?);
+    }
+
+    // Only which lines are selected matters here, and for -l only whether
+    // there is one.
+    searcher.before_context = 0;
+    searcher.after_context = 0;
+    let stats = searcher.search_reader(&config.pattern, reader, &mut |_: &Match| Ok(!config.files_with_matches))?;
 
-    for line in contents.lines() {
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
+    if config.files_with_matches {
+        if stats.matched_lines > 0 {
+            output::write_file_name(out, config, label)?;
         }
+    } else {
+        output::write_count(out, config, label, stats.matched_lines)?;
     }
 
-    results
+    Ok(stats)
+}
+
+/// Searches one file, or standard input when `path` is `-`. gzip, bzip2
+/// and zstd input is decompressed on the fly, and text in other encodings
+/// than UTF-8 is transcoded (see [`encoding::decode`]). Input that looks
+/// binary only gets a "Binary file matches" line instead of its contents.
+/// With `--follow` the search goes on as lines are appended, until `-m` is
+/// reached.
+fn search_file<W: Write>(config: &Config, path: &Path, label: Option<&str>, out: &mut W) -> Result<Stats, Box<dyn Error>> {
+    if config.write || config.dry_run {
+        return replace::rewrite_file(config, path, label, out);
+    }
+
+    let (reader, name): (Box<dyn BufRead>, _) = if config.follow {
+        (Box::new(BufReader::new(follow::Follower::open(path)?)), Some(path))
+    } else if path == Path::new("-") {
+        (Box::new(io::stdin().lock()), None)
+    } else {
+        (Box::new(BufReader::new(File::open(path)?)), Some(path))
+    };
+
+    let reader = decompress::decompress(reader, name, config.search_zip)?;
+    let (reader, binary) = encoding::decode(reader, config.encoding)?;
+
+    // -c and -l don't print lines, so binary input is fine for them.
+    if binary && !config.count && !config.files_with_matches {
+        let name = match label {
+            Some(label) => label.to_string(),
+            None if path == Path::new("-") => "(standard input)".to_string(),
+            None => path.display().to_string(),
+        };
+
+        return search_binary(config, &name, reader, out);
+    }
+
+    search_reader(config, label, reader, out)
+}
+
+/// Searches input that looks binary. Its lines would only dump garbage on
+/// the terminal, so the search stops at the first selected line and just
+/// reports that `name` matches, as grep does.
+fn search_binary<R: BufRead, W: Write>(config: &Config, name: &str, mut reader: R, out: &mut W) -> Result<Stats, Box<dyn Error>> {
+    let mut stats = Stats { files_searched: 1, ..Stats::default() };
+    let mut buffer = String::new();
+
+    while reader.read_line(&mut buffer)? > 0 {
+        let hits = config.pattern.find_all(trim_line_ending(&buffer));
+
+        if hits.is_empty() == config.invert_match {
+            stats = Stats { files_searched: 1, files_with_matches: 1, matched_lines: 1, matches: hits.len() };
+            output::write_binary_match(out, config, name)?;
+            break;
+        }
+
+        buffer.clear();
+    }
+
+    Ok(stats)
+}
+
+pub(crate) fn trim_line_ending(line: &str) -> &str {
+    match line.strip_suffix('\n') {
+        Some(line) => line.strip_suffix('\r').unwrap_or(line),
+        None => line,
+    }
+}
+
+/// Splits `contents` into lines like `str::lines`, also yielding the byte
+/// offset each line starts at.
+pub(crate) fn lines_with_offsets(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut offset = 0;
+
+    contents.split_inclusive('\n').map(move |raw| {
+        let start = offset;
+        offset += raw.len();
+
+        (start, trim_line_ending(raw))
+    })
+}
+
+/// Lines of `contents` containing `query`, found with a
+/// [`LiteralMatcher`] run over all of `contents` at once.
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    LiteralMatcher::new(&[query.to_string()]).expect("a single literal always builds").lines(contents)
+}
+
+/// Lines of `contents` containing `query` under full case folding, as
+/// [`CaseInsensitiveMatcher`] matches it.
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    let matcher = CaseInsensitiveMatcher::new(&[query.to_string()]).expect("a single literal always builds");
+
+    matching_lines(&matcher, contents)
+}
+
+/// Lines containing `query` within `max_distance` insertions, deletions
+/// or substitutions, each with the smallest such distance.
+pub fn search_fuzzy<'a>(query: &str, contents: &'a str, max_distance: usize) -> Vec<(&'a str, usize)> {
+    let matcher = FuzzyMatcher::new(&[query.to_string()], max_distance, true);
+
+    contents.lines().filter_map(|line| Some((line, matcher.distance(line)?))).collect()
+}
+
+/// Lines where `query` appears as a whole word or makes up the whole line,
+/// depending on `boundary`, matching case the way [`search`] or
+/// [`search_case_insensitive`] do.
+pub fn search_bounded<'a>(query: &str, contents: &'a str, case_sensitive: bool, boundary: Boundary) -> Vec<&'a str> {
+    let matcher = BoundedMatcher::new(&[query.to_string()], case_sensitive, boundary).expect("a single literal always builds");
+
+    matching_lines(&matcher, contents)
+}
+
+pub fn search_regex<'a>(pattern: &Pattern, contents: &'a str) -> Vec<&'a str> {
+    matching_lines(pattern, contents)
+}
+
+/// Lines of `contents` with a hit of `matcher`.
+fn matching_lines<'a>(matcher: &impl Matcher, contents: &'a str) -> Vec<&'a str> {
+    contents.lines().filter(|line| matcher.is_match(line)).collect()
+}
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use crate::{Config, ConfigError, Invocation};
+
+    pub(crate) fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// The config for `args` alone, without settings files or the
+    /// environment.
+    pub(crate) fn config(args: &[&str]) -> Config {
+        run(Config::with_files(&self::args(args), &[]))
+    }
+
+    /// The config from a command line that should start a search.
+    pub(crate) fn run(parsed: Result<Invocation, ConfigError>) -> Config {
+        match parsed {
+            Ok(Invocation::Run(config)) => *config,
+            Ok(_) => panic!("expected a search, not help, version or --print-config"),
+            Err(e) => panic!("{}", e),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support;
 
     #[test]
     fn case_sensitive() {
@@ -102,4 +532,522 @@ mod tests {
 
         assert_eq!(vec!["Test: this is a test"], search_case_insensitive(query, contents));
     }
+
+    #[test]
+    fn fast_literal_paths_keep_line_semantics() {
+        assert_eq!(vec!["b\r"], search("b\r", "ab\r\nb\r"));
+        assert_eq!(vec!["a", "b"], search("", "a\r\nb"));
+        assert!(search("a\nb", "a\nb").is_empty());
+        assert_eq!(vec!["xx", "x"], search("x", "xx\ny\nx"));
+
+        let contents = "straße\nStrasse\nSTRAẞE\nstrase";
+        assert_eq!(vec!["straße", "Strasse", "STRAẞE"], search_case_insensitive("STRASSE", contents));
+        assert_eq!(vec!["SS", "ss"], search_case_insensitive("ß", "SS\nss\nS"));
+        assert_eq!(vec!["É"], search_case_insensitive("é", "E\nÉ"));
+
+        let pattern = Pattern::literal(&["ẞ".to_string(), "Ok".to_string()], false, Boundary::Substring).unwrap();
+        assert_eq!(vec![(1..3, 0), (4..6, 1)], pattern.find_all("xSSxOK"));
+        assert_eq!(vec![(1..3, 0)], pattern.find_all("xßx"));
+        assert_eq!(vec![(7..9, 1)], pattern.find_all("café, ok"));
+
+        let pattern = Pattern::literal(&["café".to_string(), "ss".to_string()], false, Boundary::Substring).unwrap();
+        assert_eq!(vec![(0..5, 0), (7..9, 1)], pattern.find_all("CAFÉ, SS"));
+    }
+
+    #[test]
+    fn fuzzy() {
+        let query = "color";
+        let contents = "\
+colour of money
+color wheel
+cooler
+nothing here";
+
+        assert_eq!(vec![("colour of money", 1), ("color wheel", 0)], search_fuzzy(query, contents, 1));
+        assert_eq!(vec![("color wheel", 0)], search_fuzzy(query, contents, 0));
+    }
+
+    #[test]
+    fn fuzzy_spans_and_distances() {
+        let config = test_support::config(&["minigrep", "-i", "--fuzzy", "2", "recieve", "f"]);
+        let found = find_matches(&config, "We recieve mail
+RECEIVED
+relieve
+nope");
+
+        let summary: Vec<_> = found.iter().map(|found| (found.line_number, found.matched_text(), found.distance)).collect();
+        assert_eq!(vec![(1, "recieve", Some(0)), (2, "RECEIVE", Some(2)), (3, "relieve", Some(1))], summary);
+        assert_eq!(vec!["1:4:~0:We recieve mail"], output(&config, None, "We recieve mail"));
+
+        let config = test_support::config(&["minigrep", "--fuzzy", "1", "color", "f"]);
+        assert_eq!("colour", find_matches(&config, "the colour red")[0].matched_text());
+    }
+
+    #[test]
+    fn whole_words() {
+        let query = "test";
+        let contents = "\
+test passed
+testing
+contest
+the test.
+Test_case
+über Test
+Testüber";
+
+        assert_eq!(vec!["test passed", "the test."], search_bounded(query, contents, true, Boundary::Word));
+        assert_eq!(
+            vec!["test passed", "the test.", "über Test"],
+            search_bounded(query, contents, false, Boundary::Word)
+        );
+    }
+
+    #[test]
+    fn whole_lines() {
+        let contents = "\
+Straße
+straße ist lang
+ straße
+STRASSE";
+
+        assert_eq!(vec!["Straße"], search_bounded("Straße", contents, true, Boundary::Line));
+        assert_eq!(vec!["Straße", "STRASSE"], search_bounded("strasse", contents, false, Boundary::Line));
+    }
+
+    #[test]
+    fn boundaries_with_several_patterns_and_regexes() {
+        let config = test_support::config(&["minigrep", "-w", "-e", "foo", "-e", "foobar", "f"]);
+        assert_eq!(vec![(0..6, 1)], config.pattern.find_all("foobar"));
+
+        let config = test_support::config(&["minigrep", "-w", "--regex", r"\d+|c\+\+", "f"]);
+        assert_eq!(vec![4..7, 12..15], config.pattern.find_iter("abc 123 x45 c++"));
+
+        let config = test_support::config(&["minigrep", "-x", "-i", "--regex", "error.*", "f"]);
+        assert!(config.pattern.is_match("Error: disk full"));
+        assert!(!config.pattern.is_match("An error"));
+    }
+
+    #[test]
+    fn regex_case_sensitive() {
+        let pattern = Pattern::new(r"ERROR \d{3}", true).unwrap();
+        let contents =
+        "\
+ERROR 500 upstream timed out
+error 404 not found
+ERROR: disk full
+        ";
+
+        assert_eq!(vec!["ERROR 500 upstream timed out"], search_regex(&pattern, contents));
+    }
+
+    #[test]
+    fn regex_case_insensitive() {
+        let pattern = Pattern::new(r"ERROR \d{3}", false).unwrap();
+        let contents =
+        "\
+ERROR 500 upstream timed out
+error 404 not found
+ERROR: disk full
+        ";
+
+        assert_eq!(
+            vec!["ERROR 500 upstream timed out", "error 404 not found"],
+            search_regex(&pattern, contents)
+        );
+    }
+
+    #[test]
+    fn case_insensitive_unicode_folding() {
+        let contents = "\
+Straße
+ΟΔΟΣ
+İstanbul
+ılık";
+
+        assert_eq!(vec!["Straße"], search_case_insensitive("STRASSE", contents));
+        assert_eq!(vec!["ΟΔΟΣ"], search_case_insensitive("οδος", contents));
+        assert_eq!(vec!["ΟΔΟΣ"], search_case_insensitive("οδοσ", contents));
+        assert_eq!(vec!["İstanbul"], search_case_insensitive("ISTANBUL", contents));
+        assert_eq!(vec!["ılık"], search_case_insensitive("ILIK", contents));
+        assert!(search_case_insensitive("stras", "Straße").is_empty());
+        assert_eq!(vec!["sß"], search_case_insensitive("ss", "sß"));
+    }
+
+    #[test]
+    fn matches_carry_positions() {
+        let config = test_support::config(&["minigrep", "duct", "poem.txt"]);
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me, it's a product.";
+
+        let matches = find_matches(&config, contents);
+
+        assert_eq!(2, matches.len());
+        assert_eq!(2, matches[0].line_number);
+        assert_eq!(6, matches[0].byte_offset);
+        assert_eq!(16, matches[0].column());
+        assert_eq!("duct", matches[0].matched_text());
+        assert_eq!(4, matches[1].line_number);
+        assert_eq!(vec![20..24], matches[1].spans);
+    }
+
+    #[test]
+    fn case_insensitive_span_points_into_original_line() {
+        let find = |query: &str, line: &str| Pattern::literal(&[query.to_string()], false, Boundary::Substring).unwrap().find(line);
+
+        assert_eq!(Some(4..8), find("rust", "Ⱥ  RUST"));
+        assert_eq!(Some(0..4), find("TEST", "test"));
+        assert_eq!(None, find("tests", "test"));
+        assert_eq!(Some(4..6), find("SS", "Straße"));
+        assert_eq!(Some(0..8), find("strasse", "STRAẞE"));
+    }
+
+    #[test]
+    fn context_lines_with_separators() {
+        let config = test_support::config(&["minigrep", "-C", "1", "hit", "log.txt"]);
+        let contents = "\
+one
+hit two
+three
+four
+five
+hit six
+seven";
+
+        assert_eq!(
+            vec!["1-one", "2:1:hit two", "3-three", "--", "5-five", "6:1:hit six", "7-seven"],
+            output(&config, None, contents)
+        );
+    }
+
+    #[test]
+    fn overlapping_context_is_merged() {
+        let config = test_support::config(&["minigrep", "-A", "2", "hit", "log.txt"]);
+        let contents = "hit\nhit\nend\nafter\nlast";
+
+        assert_eq!(
+            vec!["f:1:1:hit", "f:2:1:hit", "f-3-end", "f-4-after"],
+            output(&config, Some("f"), contents)
+        );
+    }
+
+    #[test]
+    fn streaming_finds_the_same_lines_as_search() {
+        let config = test_support::config(&["minigrep", "-i", "rust", "poem.txt"]);
+        let contents = "Rust:\r\nsafe, fast, productive.\r\nTrust me.\nno match\nrUsT";
+
+        let streamed: Vec<String> = output(&config, None, contents)
+            .iter()
+            .map(|line| line.splitn(3, ':').nth(2).unwrap().to_string())
+            .collect();
+
+        assert_eq!(search_case_insensitive("rust", contents), streamed);
+    }
+
+    #[test]
+    fn every_hit_in_a_line_is_found() {
+        let config = test_support::config(&["minigrep", "-i", "ab", "file"]);
+
+        assert_eq!(vec![0..2, 3..5, 6..8], config.pattern.find_iter("ab AB aB b"));
+        assert!(config.pattern.find_iter("a b").is_empty());
+    }
+
+    #[test]
+    fn any_of_several_literals() {
+        let patterns = ["error".to_string(), "warn".to_string()];
+        let pattern = Pattern::literal(&patterns, true, Boundary::Substring).unwrap();
+
+        assert_eq!(vec![(0..4, 1), (9..14, 0)], pattern.find_all("warn and error"));
+        assert!(!pattern.is_match("ERROR"));
+
+        let pattern = Pattern::literal(&patterns, false, Boundary::Substring).unwrap();
+        assert_eq!(vec![(0..5, 0), (6..10, 1)], pattern.find_all("ERROR WaRn"));
+    }
+
+    #[test]
+    fn several_literals_fold_case() {
+        let patterns = ["strasse".to_string(), "s".to_string()];
+        let pattern = Pattern::literal(&patterns, false, Boundary::Substring).unwrap();
+
+        // The `s` folded out of `ß` isn't a hit on its own.
+        assert_eq!(vec![(0..7, 0)], pattern.find_all("Straße ß"));
+    }
+
+    #[test]
+    fn any_of_several_regexes() {
+        let patterns = [r"(a)(b)c".to_string(), r"\d+".to_string(), "(x|y)z".to_string()];
+        let pattern = Pattern::regex(&patterns, true, Boundary::Substring).unwrap();
+
+        assert_eq!(vec![(0..3, 0), (4..6, 1), (7..9, 2)], pattern.find_all("abc 42 yz"));
+    }
+
+    #[test]
+    fn replace_in_both_case_modes() {
+        let patterns = ["fast".to_string()];
+
+        let pattern = Pattern::literal(&patterns, true, Boundary::Substring).unwrap();
+        assert_eq!("quick, Fast, quick", pattern.replace_all("fast, Fast, fast", "quick"));
+
+        let pattern = Pattern::literal(&patterns, false, Boundary::Substring).unwrap();
+        assert_eq!("quick, quick, quick", pattern.replace_all("fast, Fast, fast", "quick"));
+
+        let pattern = Pattern::new(r"(\w+)@(\w+)", true).unwrap();
+        assert_eq!("mail bob at example", pattern.replace_all("mail bob@example", "$1 at $2"));
+    }
+
+    #[test]
+    fn replacement_is_shown_in_output() {
+        let config = test_support::config(&["minigrep", "-i", "--replace", "slow", "FAST", "file"]);
+
+        assert_eq!(vec!["1:7:safe, slow, slow."], output(&config, None, "safe, fast, Fast.\nnope"));
+    }
+
+    #[test]
+    fn json_records() {
+        let config = test_support::config(&["minigrep", "--output", "json", "-A", "1", "fast", "file"]);
+        let contents = "safe, fast, fast.\nnext\nslow";
+
+        let mut out = Vec::new();
+        let stats = search_reader(&config, Some("poem.txt"), contents.as_bytes(), &mut out).unwrap();
+        let records: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(
+            serde_json::json!({
+                "type": "match",
+                "file": "poem.txt",
+                "line_number": 1,
+                "column": 7,
+                "byte_offset": 0,
+                "text": "safe, fast, fast.",
+                "submatches": [
+                    { "text": "fast", "start": 6, "end": 10, "pattern": "fast" },
+                    { "text": "fast", "start": 12, "end": 16, "pattern": "fast" },
+                ],
+            }),
+            records[0]
+        );
+        assert_eq!("context", records[1]["type"]);
+        assert_eq!(2, records.len());
+        assert_eq!(Stats { files_searched: 1, files_with_matches: 1, matched_lines: 1, matches: 2 }, stats);
+    }
+
+    #[test]
+    fn invert_match() {
+        let config = test_support::config(&["minigrep", "-v", "duct", "poem.txt"]);
+        let contents = "Rust:\nsafe, fast, productive.\nPick three.";
+
+        assert_eq!(vec!["1:1:Rust:", "3:1:Pick three."], output(&config, None, contents));
+    }
+
+    #[test]
+    fn count_per_file() {
+        let config = test_support::config(&["minigrep", "-c", "-C", "2", "a", "file"]);
+        let contents = "a\nb\na a\nc";
+
+        assert_eq!(vec!["2"], output(&config, None, contents));
+        assert_eq!(vec!["f:2"], output(&config, Some("f"), contents));
+
+        let config = test_support::config(&["minigrep", "-c", "-v", "a", "file"]);
+        assert_eq!(vec!["f:2"], output(&config, Some("f"), contents));
+    }
+
+    #[test]
+    fn files_with_matches() {
+        let config = test_support::config(&["minigrep", "-l", "-c", "a", "file"]);
+
+        assert_eq!(vec!["f"], output(&config, Some("f"), "b\na\na"));
+        assert!(output(&config, Some("f"), "b\nc").is_empty());
+    }
+
+    #[test]
+    fn max_count_keeps_trailing_context() {
+        let config = test_support::config(&["minigrep", "-m", "2", "-A", "1", "a", "file"]);
+        let contents = "a1\nb\nc\na2\na3\na4";
+
+        assert_eq!(vec!["1:1:a1", "2-b", "--", "4:1:a2", "5-a3"], output(&config, None, contents));
+
+        let config = test_support::config(&["minigrep", "-c", "--max-count=1", "a", "file"]);
+        assert_eq!(vec!["1"], output(&config, None, contents));
+    }
+
+    #[test]
+    fn compressed_files_are_searched() {
+        let config = test_support::config(&["minigrep", "-z", "ERROR", "file"]);
+
+        for name in ["log.txt.gz", "log.txt.bz2", "log.txt.zst"] {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+
+            let mut out = Vec::new();
+            let stats = search_file(&config, &path, None, &mut out).unwrap();
+
+            assert_eq!("2:1:ERROR 500 inside a compressed log\n", String::from_utf8(out).unwrap());
+            assert_eq!(1, stats.matched_lines);
+        }
+
+        // -z still searches plain files.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain.log");
+        std::fs::write(&path, "ok\nERROR 404\n").unwrap();
+
+        let mut out = Vec::new();
+        search_file(&config, &path, None, &mut out).unwrap();
+        assert_eq!("2:1:ERROR 404\n", String::from_utf8(out).unwrap());
+
+        // Without -z, compressed files are found by their contents too.
+        let config = test_support::config(&["minigrep", "ERROR", "file"]);
+        let path = dir.path().join("archived");
+        std::fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/log.txt.gz"), &path).unwrap();
+
+        let mut out = Vec::new();
+        search_file(&config, &path, None, &mut out).unwrap();
+        assert_eq!("2:1:ERROR 500 inside a compressed log\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn binary_files_only_report_a_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bin");
+        std::fs::write(&path, b"\x7fELF\0\x01\nsome text\nmore text\n").unwrap();
+
+        let mut matching = Vec::new();
+        let config = test_support::config(&["minigrep", "text", "file"]);
+        let stats = search_file(&config, &path, Some("bin"), &mut matching).unwrap();
+
+        let mut missing = Vec::new();
+        let config = test_support::config(&["minigrep", "nowhere", "file"]);
+        search_file(&config, &path, Some("bin"), &mut missing).unwrap();
+
+        let mut counted = Vec::new();
+        let config = test_support::config(&["minigrep", "-c", "text", "file"]);
+        search_file(&config, &path, Some("bin"), &mut counted).unwrap();
+
+        assert_eq!("Binary file bin matches\n", String::from_utf8(matching).unwrap());
+        assert_eq!(1, stats.files_with_matches);
+        assert!(missing.is_empty());
+        assert_eq!("bin:2\n", String::from_utf8(counted).unwrap());
+    }
+
+    #[test]
+    fn other_encodings_are_transcoded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("text");
+
+        let mut latin1 = Vec::new();
+        std::fs::write(&path, b"d\xe9j\xe0 vu\nnothing\n").unwrap();
+        let config = test_support::config(&["minigrep", "déjà", "file"]);
+        search_file(&config, &path, None, &mut latin1).unwrap();
+
+        let mut utf16 = Vec::new();
+        let text: Vec<u8> = "\u{feff}one\r\ntwo déjà\r\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        std::fs::write(&path, text).unwrap();
+        search_file(&config, &path, None, &mut utf16).unwrap();
+
+        let mut forced = Vec::new();
+        std::fs::write(&path, b"\xe4\xee\xec\n").unwrap();
+        let config = test_support::config(&["minigrep", "--encoding", "windows-1251", "дом", "file"]);
+        search_file(&config, &path, None, &mut forced).unwrap();
+
+        assert_eq!("1:1:déjà vu\n", String::from_utf8(latin1).unwrap());
+        assert_eq!("2:5:two déjà\n", String::from_utf8(utf16).unwrap());
+        assert_eq!("1:1:дом\n", String::from_utf8(forced).unwrap());
+    }
+
+    #[test]
+    fn multiline_matches_span_lines() {
+        let contents = "\
+INFO start
+Traceback (most recent call last):
+  File \"app.py\", line 3
+  File \"db.py\", line 9
+ValueError: bad
+Traceback (most recent call last):
+  File \"web.py\", line 1
+";
+        let config = test_support::config(&["minigrep", "-U", "--regex", r"Traceback.*\n(?:  .*\n)*?  File .db\.py", "f"]);
+        let found = find_matches(&config, contents);
+
+        assert_eq!(1, found.len());
+        assert_eq!((2, 4), (found[0].line_number, found[0].end_line_number));
+        assert_eq!(11, found[0].byte_offset);
+        assert!(found[0].line.ends_with("line 9"));
+        assert_eq!(
+            vec!["2-4:1:Traceback (most recent call last):", "3:  File \"app.py\", line 3", "4:  File \"db.py\", line 9"],
+            output(&config, None, contents)
+        );
+
+        // Hits on the same lines are printed once.
+        let config = test_support::config(&["minigrep", "-U", "-C", "1", "--regex", r"ValueError|bad\nTrace", "f"]);
+        assert_eq!(
+            vec!["4-  File \"db.py\", line 9", "5-6:1:ValueError: bad", "6:Traceback (most recent call last):", "7-  File \"web.py\", line 1"],
+            output(&config, None, contents)
+        );
+    }
+
+    #[test]
+    fn multiline_anchors_and_literals() {
+        let contents = "one\r\ntwo\nthree";
+
+        let config = test_support::config(&["minigrep", "-U", "--regex", "^two$", "f"]);
+        assert_eq!(vec!["2:1:two"], output(&config, None, contents));
+
+        let config = test_support::config(&["minigrep", "-U", "-x", "-c", "one", "f"]);
+        assert_eq!(vec!["1"], output(&config, None, contents));
+
+        let config = test_support::config(&["minigrep", "-U", "-i", "--output", "json", "TWO\nTH", "f"]);
+        let record: serde_json::Value = serde_json::from_str(&output(&config, None, contents)[0]).unwrap();
+        assert_eq!((2, 3), (record["line_number"].as_u64().unwrap(), record["end_line_number"].as_u64().unwrap()));
+        assert_eq!("two\nth", record["submatches"][0]["text"]);
+    }
+
+    #[test]
+    fn field_queries_on_json_lines() {
+        let contents = r#"{"level":"error","msg":"disk full","req":{"user":"alice"}}
+{"level":"info","msg":"error count reset","req":{"user":"bob"}}
+plain error line
+{"level":"ERROR","msg":"timeout","req":{"user":"malice"}}"#;
+
+        let config = test_support::config(&["minigrep", "-s", "--field", "level=error", "f"]);
+        assert_eq!(vec!["1:11:{\"level\":\"error\",\"msg\":\"disk full\",\"req\":{\"user\":\"alice\"}}", "3:7:plain error line"], output(&config, None, contents));
+
+        let config = test_support::config(&["minigrep", "-i", "--field", "level=error", "--field", "req.user~alice", "-c", "f"]);
+        // Both records, and the plain line that mentions either value.
+        assert_eq!(vec!["3"], output(&config, None, contents));
+
+        let config = test_support::config(&["minigrep", "-s", "--regex", "--field", "req.user~^b", "f"]);
+        let found = find_matches(&config, contents);
+        assert_eq!(vec![(2, "b")], found.iter().map(|found| (found.line_number, found.matched_text())).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn run_writes_to_any_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(root.join("b.txt"), "three\n").unwrap();
+
+        let dir = root.display().to_string();
+        let mut out = Vec::new();
+        run_to(test_support::config(&["minigrep", "-s", "--color", "never", "t", &dir]), &mut out).unwrap();
+
+        let file = root.join("a.txt").display().to_string();
+        let mut single = Vec::new();
+        run_to(test_support::config(&["minigrep", "-s", "-c", "o", &file]), &mut single).unwrap();
+
+        let expected = format!("{}/a.txt:2:1:two\n{}/b.txt:1:1:three\n", dir, dir);
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+        assert_eq!("Result: \n2\n", String::from_utf8(single).unwrap());
+    }
+
+    fn output(config: &Config, label: Option<&str>, contents: &str) -> Vec<String> {
+        let mut out = Vec::new();
+        search_reader(config, label, contents.as_bytes(), &mut out).unwrap();
+
+        String::from_utf8(out).unwrap().lines().map(String::from).collect()
+    }
 }
@@ -3,18 +3,38 @@ use std::{
     process, 
 };
 
-use minigrep::Config;
+use minigrep::{Command, Config, Invocation, OutputFormat, USAGE};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    let config = Config::new(&args).unwrap_or_else(|err| {
-        eprintln!("Error parsing arguments:\n {}", err);
-        process::exit(1);
-    });
+    let config = match Config::new(&args) {
+        Ok(Invocation::Run(config)) => *config,
+        Ok(Invocation::Help) => {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        Ok(Invocation::Version) => {
+            println!("minigrep {}", env!("CARGO_PKG_VERSION"));
+            process::exit(0);
+        }
+        Ok(Invocation::PrintConfig(description)) => {
+            println!("{}", description);
+            process::exit(0);
+        }
+        Err(err) => {
+            eprintln!("Error parsing arguments:\n {}", err);
+            eprintln!("Try 'minigrep --help' for more information.");
+            process::exit(1);
+        }
+    };
 
-    println!("Search for: {:?}", config.query);
-    println!("In File: {:?}", config.file_name);
+    // Keep stdout clean for tools reading JSON or the line picked in
+    // --interactive mode.
+    if config.command == Command::Search && config.output == OutputFormat::Text && !config.interactive {
+        println!("Search for: {:?}", config.patterns);
+        println!("In File: {:?}", config.file_names);
+    }
 
     if let Err( e ) = minigrep::run(config) {
         eprintln!("Application Error:\n {}", e);
@@ -0,0 +1,620 @@
+use std::{borrow::Cow, ops::Range};
+
+use aho_corasick::{AhoCorasick, Input, MatchKind};
+use caseless::Caseless;
+use memchr::memmem::Finder;
+use regex::{Captures, Regex, RegexBuilder};
+
+use crate::{Boundary, Matcher};
+
+/// Literal strings matched exactly. A single string is looked for with
+/// memchr's SIMD substring search, several all at once with Aho–Corasick.
+pub struct LiteralMatcher {
+    searcher: Literals,
+}
+
+enum Literals {
+    One(Box<Finder<'static>>),
+    Many(AhoCorasick),
+}
+
+impl LiteralMatcher {
+    pub fn new(patterns: &[String]) -> Result<LiteralMatcher, aho_corasick::BuildError> {
+        // An empty string hits between every two bytes, which Aho–Corasick
+        // reports the same way whatever the line holds.
+        let searcher = match patterns {
+            [pattern] if !pattern.is_empty() => Literals::One(Box::new(Finder::new(pattern).into_owned())),
+            patterns => Literals::Many(AhoCorasick::builder().match_kind(MatchKind::LeftmostFirst).build(patterns)?),
+        };
+
+        Ok(LiteralMatcher { searcher })
+    }
+
+    /// The lines of `contents` with a hit, split as `str::lines` splits
+    /// them. Rather than going line by line, the search runs over all of
+    /// `contents` and each hit is widened to its line, so lines without
+    /// one cost next to nothing.
+    pub(crate) fn lines<'a>(&self, contents: &'a str) -> Vec<&'a str> {
+        let bytes = contents.as_bytes();
+        let mut results = Vec::new();
+        let mut at = 0;
+
+        while at < bytes.len() {
+            let Some(hit) = self.find(&contents[at..]).map(|span| at + span.start..at + span.end) else {
+                break;
+            };
+            let start = memchr::memrchr(b'\n', &bytes[..hit.start]).map_or(0, |newline| newline + 1);
+            let end = memchr::memchr(b'\n', &bytes[hit.start..]).map_or(bytes.len(), |newline| hit.start + newline);
+
+            // As with `str::lines`, `\r` only ends a line before `\n`, and a
+            // hit can't run into a line ending.
+            let line = &contents[start..end];
+            let line = if end < bytes.len() { line.strip_suffix('\r').unwrap_or(line) } else { line };
+            if hit.end <= start + line.len() || self.is_match(line) {
+                results.push(line);
+            }
+
+            at = end + 1;
+        }
+
+        results
+    }
+
+    /// The first hit in `haystack`.
+    fn find(&self, haystack: &str) -> Option<Range<usize>> {
+        match &self.searcher {
+            Literals::One(finder) => finder.find(haystack.as_bytes()).map(|start| start..start + finder.needle().len()),
+            Literals::Many(searcher) => searcher.find(haystack).map(|found| found.range()),
+        }
+    }
+}
+
+impl Matcher for LiteralMatcher {
+    fn find_all(&self, line: &str) -> Vec<(Range<usize>, usize)> {
+        match &self.searcher {
+            Literals::One(finder) => {
+                let len = finder.needle().len();
+                finder.find_iter(line.as_bytes()).map(|start| (start..start + len, 0)).collect()
+            }
+            Literals::Many(searcher) => searcher.find_iter(line).map(|found| (found.range(), found.pattern().as_usize())).collect(),
+        }
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        self.find(line).is_some()
+    }
+}
+
+/// Literal strings matched under full Unicode case folding, so `STRASSE`
+/// finds `straße` and `ΟΔΟΣ` finds `οδος`. Hits always point into the
+/// original line and never start or end partway through a character.
+///
+/// The patterns are stored case-folded and each line is folded before
+/// searching, unless it can be searched as it is (see [`AsciiFolded`]).
+pub struct CaseInsensitiveMatcher {
+    searcher: AhoCorasick,
+    ascii: AsciiFolded,
+}
+
+impl CaseInsensitiveMatcher {
+    pub fn new(patterns: &[String]) -> Result<CaseInsensitiveMatcher, aho_corasick::BuildError> {
+        let folded: Vec<String> = patterns.iter().map(|pattern| pattern.chars().flat_map(fold_case).collect()).collect();
+        let searcher = AhoCorasick::builder().match_kind(MatchKind::LeftmostFirst).build(&folded)?;
+        let ascii = AsciiFolded {
+            searcher: AhoCorasick::builder().match_kind(MatchKind::LeftmostFirst).ascii_case_insensitive(true).build(&folded)?,
+            all_ascii: folded.iter().all(|pattern| pattern.is_ascii()),
+        };
+
+        Ok(CaseInsensitiveMatcher { searcher, ascii })
+    }
+}
+
+impl Matcher for CaseInsensitiveMatcher {
+    fn find_all(&self, line: &str) -> Vec<(Range<usize>, usize)> {
+        if self.ascii.applies(line) {
+            return self.ascii.searcher.find_iter(line).map(|found| (found.range(), found.pattern().as_usize())).collect();
+        }
+
+        let (folded, origins) = fold_line(line);
+        // The first offset after `at` where a character's folding starts.
+        let next_char = |at: usize| origins[at + 1..].iter().position(Option::is_some).map(|skip| at + 1 + skip);
+
+        let mut hits = Vec::new();
+        let mut at = Some(0);
+
+        // A hit that starts or ends partway through the folding of a single
+        // character, like `s` inside `ß`, isn't one. The search then goes on
+        // from the next character, where a hit overlapping it may start.
+        while let Some(found) = at.and_then(|at| self.searcher.find(Input::new(&folded).span(at..folded.len()))) {
+            match (origins[found.start()], origins[found.end()]) {
+                (Some(start), Some(end)) => {
+                    hits.push((start..end, found.pattern().as_usize()));
+                    at = if found.is_empty() { next_char(found.end()) } else { Some(found.end()) };
+                }
+                _ => at = next_char(found.start()),
+            }
+        }
+
+        hits
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        if self.ascii.applies(line) {
+            return self.ascii.searcher.is_match(line);
+        }
+
+        !self.find_all(line).is_empty()
+    }
+}
+
+/// Case-folded literals matched by ignoring ASCII case instead of folding
+/// each line. That finds the same hits in ASCII lines, which folding only
+/// lowercases, and, when the patterns are all ASCII, in any line without
+/// one of the [`FOLDS_TO_ASCII`] characters, since nothing else in it can
+/// fold into part of a hit.
+struct AsciiFolded {
+    searcher: AhoCorasick,
+    all_ascii: bool,
+}
+
+impl AsciiFolded {
+    fn applies(&self, line: &str) -> bool {
+        line.is_ascii() || (self.all_ascii && !folds_to_ascii(line))
+    }
+}
+
+/// Full case folding of a single character. Without knowing the locale,
+/// Turkish dotted `İ` and dotless `ı` are treated as a plain `i` so that
+/// Turkish text matches either way it is typed.
+pub(crate) fn fold_case(c: char) -> impl Iterator<Item = char> {
+    let c = match c {
+        '\u{130}' | '\u{131}' => 'i',
+        c => c,
+    };
+
+    std::iter::once(c).default_case_fold()
+}
+
+/// Case-folds a whole line. Alongside the folded text, returns for every
+/// byte offset into it (plus its end) the offset in `line` of the character
+/// whose folding starts there, or `None` partway through a character.
+fn fold_line(line: &str) -> (String, Vec<Option<usize>>) {
+    let mut folded = String::with_capacity(line.len());
+    let mut origins = Vec::with_capacity(line.len() + 1);
+
+    for (index, c) in line.char_indices() {
+        // ASCII only lowercases, without going through the fold tables.
+        if c.is_ascii() {
+            folded.push(c.to_ascii_lowercase());
+            origins.push(Some(index));
+            continue;
+        }
+
+        folded.extend(fold_case(c));
+
+        origins.push(Some(index));
+        origins.resize(folded.len(), None);
+    }
+    origins.push(Some(line.len()));
+
+    (folded, origins)
+}
+
+/// The characters outside ASCII that [`fold_case`] turns into something
+/// with ASCII in it, like `ß` into `ss` or the Kelvin sign into `k`.
+const FOLDS_TO_ASCII: [char; 20] = [
+    'ß', 'İ', 'ı', 'ŉ', 'ſ', 'ǰ', 'ẖ', 'ẗ', 'ẘ', 'ẙ', 'ẚ', 'ẞ', '\u{212a}', 'ﬀ', 'ﬁ', 'ﬂ', 'ﬃ', 'ﬄ', 'ﬅ', 'ﬆ',
+];
+
+/// Whether `line` has any of the [`FOLDS_TO_ASCII`] characters. Only the
+/// characters outside ASCII are decoded to check.
+pub(crate) fn folds_to_ascii(line: &str) -> bool {
+    let mut rest = line;
+
+    while let Some(at) = rest.bytes().position(|byte| !byte.is_ascii()) {
+        let Some(c) = rest[at..].chars().next() else {
+            break;
+        };
+        if FOLDS_TO_ASCII.contains(&c) {
+            return true;
+        }
+        rest = &rest[at + c.len_utf8()..];
+    }
+
+    false
+}
+
+/// Literal strings that must cover a whole word or line (`-w`, `-x`),
+/// matched exactly or under the same case folding as
+/// [`CaseInsensitiveMatcher`]. Every occurrence is checked against the
+/// boundary, overlapping ones too, since the hit Aho–Corasick prefers at a
+/// position may fail it while another would not: `-w test` still finds
+/// the `test` after `contest`.
+pub struct BoundedMatcher {
+    searcher: AhoCorasick,
+    case_sensitive: bool,
+    boundary: Boundary,
+}
+
+impl BoundedMatcher {
+    pub fn new(patterns: &[String], case_sensitive: bool, boundary: Boundary) -> Result<BoundedMatcher, aho_corasick::BuildError> {
+        let searcher = if case_sensitive {
+            AhoCorasick::new(patterns)?
+        } else {
+            AhoCorasick::new(patterns.iter().map(|pattern| pattern.chars().flat_map(fold_case).collect::<String>()))?
+        };
+
+        Ok(BoundedMatcher { searcher, case_sensitive, boundary })
+    }
+}
+
+impl Matcher for BoundedMatcher {
+    fn find_all(&self, line: &str) -> Vec<(Range<usize>, usize)> {
+        // Empty patterns hit between every two bytes, and folded hits can
+        // start or end partway through a character; neither is a hit.
+        let mut hits: Vec<(Range<usize>, usize)> = if self.case_sensitive {
+            self.searcher
+                .find_overlapping_iter(line)
+                .filter(|found| line.is_char_boundary(found.start()))
+                .map(|found| (found.range(), found.pattern().as_usize()))
+                .collect()
+        } else {
+            let (folded, origins) = fold_line(line);
+
+            self.searcher
+                .find_overlapping_iter(&folded)
+                .filter_map(|found| {
+                    let start = origins[found.start()]?;
+                    let end = origins[found.end()]?;
+                    Some((start..end, found.pattern().as_usize()))
+                })
+                .collect()
+        };
+
+        hits.retain(|(span, _)| self.boundary.allows(line, span));
+        hits.sort_by_key(|(span, pattern)| (span.start, *pattern));
+
+        // Keep the leftmost hits that don't overlap, earlier patterns
+        // first, as Aho–Corasick would.
+        let mut end = 0;
+        hits.retain(|(span, _)| {
+            let keep = span.start >= end;
+            if keep {
+                end = span.end.max(span.start + 1);
+            }
+            keep
+        });
+
+        hits
+    }
+}
+
+/// Literal strings matched within `max_distance` insertions, deletions or
+/// substitutions (`--fuzzy`). Each line yields at most one hit: the
+/// closest one, with ties going to the earlier pattern.
+pub struct FuzzyMatcher {
+    /// The patterns as characters, case-folded unless `case_sensitive`.
+    queries: Vec<Vec<char>>,
+    max_distance: usize,
+    case_sensitive: bool,
+}
+
+impl FuzzyMatcher {
+    pub fn new(patterns: &[String], max_distance: usize, case_sensitive: bool) -> FuzzyMatcher {
+        let queries = patterns
+            .iter()
+            .map(|pattern| if case_sensitive { pattern.chars().collect() } else { pattern.chars().flat_map(fold_case).collect() })
+            .collect();
+
+        FuzzyMatcher { queries, max_distance, case_sensitive }
+    }
+
+    /// The closest hit of any pattern, with its pattern index and
+    /// distance.
+    fn closest(&self, line: &str) -> Option<(Range<usize>, usize, usize)> {
+        self.queries
+            .iter()
+            .enumerate()
+            .filter_map(|(pattern, query)| {
+                let (span, distance) = find_fuzzy(query, line, self.max_distance, self.case_sensitive)?;
+                Some((span, pattern, distance))
+            })
+            .min_by_key(|&(_, _, distance)| distance)
+    }
+}
+
+impl Matcher for FuzzyMatcher {
+    fn find_all(&self, line: &str) -> Vec<(Range<usize>, usize)> {
+        self.closest(line).map(|(span, pattern, _)| (span, pattern)).into_iter().collect()
+    }
+
+    fn distance(&self, line: &str) -> Option<usize> {
+        self.closest(line).map(|(_, _, distance)| distance)
+    }
+}
+
+/// Finds the substring of `line` with the smallest edit distance to
+/// `query`, if that distance is at most `max_distance`, using Sellers'
+/// algorithm: the Levenshtein table, except that a match may start at any
+/// column for free. `query` must already be case-folded when matching
+/// case-insensitively. Among equally close hits the first one wins.
+fn find_fuzzy(query: &[char], line: &str, max_distance: usize, case_sensitive: bool) -> Option<(Range<usize>, usize)> {
+    // The characters compared against `query`, each with the byte range of
+    // the character in `line` it came from.
+    let text: Vec<(char, Range<usize>)> = line
+        .char_indices()
+        .flat_map(|(start, c)| {
+            let span = start..start + c.len_utf8();
+            let chars: Vec<char> = if case_sensitive { vec![c] } else { fold_case(c).collect() };
+            chars.into_iter().map(move |c| (c, span.clone()))
+        })
+        .collect();
+
+    // `column[i]` holds the distance between the first `i` query characters
+    // and the best substring ending at the current position, and the index
+    // into `text` where that substring starts.
+    let mut column: Vec<(usize, usize)> = (0..=query.len()).map(|i| (i, 0)).collect();
+    let mut best = (query.len() <= max_distance).then_some((query.len(), 0, 0));
+
+    for (end, (c, _)) in text.iter().enumerate() {
+        let mut next = Vec::with_capacity(column.len());
+        next.push((0, end + 1));
+
+        for i in 1..=query.len() {
+            let substituted = (column[i - 1].0 + usize::from(query[i - 1] != *c), column[i - 1].1);
+            let deleted = (next[i - 1].0 + 1, next[i - 1].1);
+            let inserted = (column[i].0 + 1, column[i].1);
+
+            next.push([substituted, deleted, inserted].into_iter().min_by_key(|&(cost, _)| cost).unwrap());
+        }
+
+        column = next;
+
+        // A hit that stays as close while running on from the same start,
+        // like `colour` for `color`, is grown rather than cut short.
+        let (distance, start) = column[query.len()];
+        let better = best.is_none_or(|(closest, first, _)| distance < closest || (distance == closest && start == first));
+        if distance <= max_distance && better {
+            best = Some((distance, start, end + 1));
+            if distance == 0 {
+                break;
+            }
+        }
+    }
+
+    let (distance, start, end) = best?;
+    let span = if start == end {
+        let offset = text.get(start).map_or(line.len(), |(_, span)| span.start);
+        offset..offset
+    } else {
+        text[start].1.start..text[end - 1].1.end
+    };
+
+    Some((span, distance))
+}
+
+/// Regular expressions, joined into one alternation when there are
+/// several so a line is only scanned once. `^` and `$` also match at line
+/// breaks, which only shows when the input holds several lines.
+pub struct RegexMatcher {
+    regex: Regex,
+    /// With several patterns, `groups[i]` is the capture group wrapping
+    /// pattern `i`.
+    groups: Vec<usize>,
+}
+
+impl RegexMatcher {
+    pub fn new(patterns: &[String], case_sensitive: bool, boundary: Boundary) -> Result<RegexMatcher, regex::Error> {
+        // Half boundaries rather than `\b`, so `-w` works on patterns that
+        // start or end with punctuation, as in grep.
+        let bound = |pattern: &str| match boundary {
+            Boundary::Substring => pattern.to_string(),
+            Boundary::Word => format!(r"\b{{start-half}}(?:{})\b{{end-half}}", pattern),
+            Boundary::Line => format!("^(?:{})$", pattern),
+        };
+        let build = |pattern: &str| RegexBuilder::new(pattern).case_insensitive(!case_sensitive).multi_line(true).crlf(true).build();
+
+        if let [pattern] = patterns {
+            return Ok(RegexMatcher { regex: build(&bound(pattern))?, groups: Vec::new() });
+        }
+
+        // Wrap each pattern in a group of its own. Groups inside a pattern
+        // shift the numbering of the ones after it.
+        let mut groups = Vec::new();
+        let mut next_group = 1;
+
+        for pattern in patterns {
+            groups.push(next_group);
+            next_group += Regex::new(pattern)?.captures_len();
+        }
+
+        // An empty alternation would match everywhere; no patterns should
+        // match nothing, as it does for literals.
+        let alternation: Vec<String> = patterns.iter().map(|pattern| format!("({})", bound(pattern))).collect();
+        let alternation = if alternation.is_empty() { r"[^\s\S]".to_string() } else { alternation.join("|") };
+
+        Ok(RegexMatcher { regex: build(&alternation)?, groups })
+    }
+
+    /// Replaces every hit in `line` with `replacement`, where `$1` or
+    /// `${name}` expand to capture groups. Numbered groups count within the
+    /// pattern that hit, as if it had been given alone.
+    pub fn replace_all<'a>(&self, line: &'a str, replacement: &str) -> Cow<'a, str> {
+        if self.groups.is_empty() {
+            return self.regex.replace_all(line, replacement);
+        }
+
+        let ends = self.groups.iter().skip(1).copied().chain([self.regex.captures_len()]);
+        let replacements: Vec<String> =
+            self.groups.iter().zip(ends).map(|(&group, end)| shift_groups(replacement, group, end - group - 1)).collect();
+
+        self.regex.replace_all(line, |captures: &Captures| {
+            let Some(pattern) = self.pattern(captures) else {
+                return captures[0].to_string();
+            };
+
+            let mut replaced = String::new();
+            captures.expand(&replacements[pattern], &mut replaced);
+            replaced
+        })
+    }
+
+    /// The index of the pattern whose group took part in `captures`.
+    fn pattern(&self, captures: &Captures) -> Option<usize> {
+        self.groups.iter().position(|&group| captures.get(group).is_some())
+    }
+}
+
+/// Rewrites `replacement` for a pattern whose own groups sit right after
+/// the group `wrapper` that wraps it, and number `1..=groups` on their own:
+/// `$1` becomes `${wrapper+1}`, and numbers past `groups` expand to
+/// nothing. `$0`, `$$` and named groups are left as they are.
+fn shift_groups(replacement: &str, wrapper: usize, groups: usize) -> String {
+    let mut shifted = String::with_capacity(replacement.len());
+    let mut rest = replacement;
+
+    while let Some(at) = rest.find('$') {
+        shifted.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            shifted.push_str("$$");
+            rest = after;
+            continue;
+        }
+
+        // Names run as far as they can, as in `Captures::expand`. Without
+        // one the `$` is plain text.
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", rest),
+            },
+            None => {
+                let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+
+        if name.is_empty() {
+            shifted.push('$');
+            continue;
+        }
+
+        match name.parse::<usize>() {
+            Ok(0) | Err(_) => shifted.push_str(&format!("${{{}}}", name)),
+            Ok(group) if group <= groups => shifted.push_str(&format!("${{{}}}", wrapper + group)),
+            Ok(_) => {}
+        }
+        rest = after;
+    }
+    shifted.push_str(rest);
+
+    shifted
+}
+
+impl Matcher for RegexMatcher {
+    fn find_all(&self, line: &str) -> Vec<(Range<usize>, usize)> {
+        if self.groups.is_empty() {
+            return self.regex.find_iter(line).map(|found| (found.range(), 0)).collect();
+        }
+
+        self.regex
+            .captures_iter(line)
+            .filter_map(|captures| Some((captures.get(0)?.range(), self.pattern(&captures)?)))
+            .collect()
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        self.regex.is_match(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn literals() {
+        let one = LiteralMatcher::new(&strings(&["ab"])).unwrap();
+        assert_eq!(vec![(0..2, 0), (4..6, 0)], one.find_all("abcaab"));
+        assert!(!one.is_match("AB"));
+
+        let many = LiteralMatcher::new(&strings(&["ab", "ca"])).unwrap();
+        assert_eq!(vec![(0..2, 0), (2..4, 1), (4..6, 0)], many.find_all("abcaab"));
+
+        let empty = LiteralMatcher::new(&strings(&[""])).unwrap();
+        assert_eq!(vec![(0..0, 0), (1..1, 0)], empty.find_all("a"));
+    }
+
+    #[test]
+    fn case_insensitive_literals() {
+        let matcher = CaseInsensitiveMatcher::new(&strings(&["strasse", "ΟΔΟΣ"])).unwrap();
+
+        assert_eq!(vec![(0..7, 0)], matcher.find_all("STRASSE"));
+        assert_eq!(vec![(4..11, 0)], matcher.find_all("die Straße"));
+        assert_eq!(vec![(0..8, 1)], matcher.find_all("οδος"));
+        // Half of `ß` isn't a hit, but a hit overlapping it can be.
+        assert!(CaseInsensitiveMatcher::new(&strings(&["stras"])).unwrap().find_all("Straße").is_empty());
+        assert_eq!(vec![(1..3, 0)], CaseInsensitiveMatcher::new(&strings(&["ss"])).unwrap().find_all("sß"));
+        assert_eq!(vec![(1..3, 0)], CaseInsensitiveMatcher::new(&strings(&["ss", "zz"])).unwrap().find_all("sß"));
+        assert_eq!(vec![(0..0, 0), (2..2, 0)], CaseInsensitiveMatcher::new(&strings(&[""])).unwrap().find_all("ß"));
+    }
+
+    #[test]
+    fn bounded_literals() {
+        let words = BoundedMatcher::new(&strings(&["test", "con"]), true, Boundary::Word).unwrap();
+        assert_eq!(vec![(9..13, 0)], words.find_all("contest, test"));
+
+        // An occurrence overlapping one that fails the boundary still counts.
+        let words = BoundedMatcher::new(&strings(&["foo", "foobar"]), false, Boundary::Word).unwrap();
+        assert_eq!(vec![(0..6, 1), (7..10, 0)], words.find_all("FOOBAR foo"));
+
+        let lines = BoundedMatcher::new(&strings(&["strasse", ""]), false, Boundary::Line).unwrap();
+        assert_eq!(vec![(0..7, 0)], lines.find_all("Straße"));
+        assert_eq!(vec![(0..0, 1)], lines.find_all(""));
+        assert!(lines.find_all("ü").is_empty());
+    }
+
+    #[test]
+    fn fuzzy_literals() {
+        let matcher = FuzzyMatcher::new(&strings(&["colour", "color"]), 1, false);
+
+        assert_eq!(vec![(4..9, 1)], matcher.find_all("the COLOR red"));
+        assert_eq!(Some(0), matcher.distance("the COLOR red"));
+        assert_eq!(Some(1), matcher.distance("colr"));
+        assert_eq!(None, matcher.distance("cooler"));
+    }
+
+    #[test]
+    fn folds_to_ascii_is_complete() {
+        let expected: Vec<char> = (0x80..=0x10ffff)
+            .filter_map(char::from_u32)
+            .filter(|&c| fold_case(c).any(|folded| folded.is_ascii()))
+            .collect();
+
+        let mut table = FOLDS_TO_ASCII.to_vec();
+        table.sort();
+        assert_eq!(expected, table);
+    }
+
+    #[test]
+    fn regexes() {
+        let matcher = RegexMatcher::new(&strings(&[r"(\d+)", "x"]), false, Boundary::Word).unwrap();
+
+        assert_eq!(vec![(0..1, 1), (2..4, 0)], matcher.find_all("X 42 x42"));
+        assert_eq!("[x] [42] x42", matcher.replace_all("x 42 x42", "[$0]"));
+
+        // `$1` is each pattern's own first group, not the one wrapping it.
+        let matcher = RegexMatcher::new(&strings(&[r"(\w+)@x", r"(\d)(\d)", "y"]), true, Boundary::Substring).unwrap();
+        assert_eq!("<bob> <21> <>", matcher.replace_all("bob@x 12 y", "<$2$1>"));
+        assert_eq!("$1 1, 2 [12] ${ $", matcher.replace_all("12", "$$1 ${1}, $2 [$0] ${ $"));
+
+        let lines = RegexMatcher::new(&strings(&["b"]), true, Boundary::Line).unwrap();
+        assert_eq!(vec![(2..3, 0)], lines.find_all("a\nb\r\nbb"));
+    }
+}
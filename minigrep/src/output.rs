@@ -0,0 +1,347 @@
+use std::{
+    io::{self, Write},
+    ops::Range,
+    time::Duration,
+};
+
+use serde_json::json;
+
+use crate::{Config, Match, Sink};
+
+/// How results are written to stdout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// grep-style `path:line:column:text` lines.
+    Text,
+    /// One JSON object per line, followed by a summary record.
+    Json,
+}
+
+/// Totals for one or more searched inputs.
+#[derive(Debug, Default, PartialEq)]
+pub struct Stats {
+    pub files_searched: usize,
+    pub files_with_matches: usize,
+    pub matched_lines: usize,
+    pub matches: usize,
+}
+
+impl Stats {
+    pub fn add(&mut self, other: &Stats) {
+        self.files_searched += other.files_searched;
+        self.files_with_matches += other.files_with_matches;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+}
+
+/// When to highlight text output with ANSI escape codes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorChoice {
+    Always,
+    Never,
+    /// Color when stdout is a terminal. [`crate::run`] resolves this before
+    /// searching; anywhere else it behaves like `Never`.
+    Auto,
+}
+
+// The same defaults GNU grep uses for GREP_COLORS.
+const FILE_NAME_COLOR: &str = "35";
+const LINE_NUMBER_COLOR: &str = "32";
+const MATCH_COLOR: &str = "1;31";
+const SEPARATOR_COLOR: &str = "36";
+
+/// Wraps `text` in the escape codes for `color` when `config` asks for it.
+fn paint(config: &Config, color: &str, text: &str) -> String {
+    if config.color == ColorChoice::Always && !text.is_empty() {
+        format!("\x1b[{}m{}\x1b[0m", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Renders `line` with every span in `spans` highlighted.
+fn highlight(config: &Config, line: &str, spans: &[Range<usize>]) -> String {
+    if config.color != ColorChoice::Always {
+        return line.to_string();
+    }
+
+    let mut highlighted = String::with_capacity(line.len());
+    let mut written = 0;
+
+    for span in spans {
+        highlighted.push_str(&line[written..span.start]);
+        highlighted.push_str(&paint(config, MATCH_COLOR, &line[span.clone()]));
+        written = span.end;
+    }
+    highlighted.push_str(&line[written..]);
+
+    highlighted
+}
+
+pub(crate) fn write_match<W: Write>(
+    out: &mut W,
+    config: &Config,
+    label: Option<&str>,
+    found: &Match,
+) -> io::Result<()> {
+    match config.output {
+        OutputFormat::Text => {
+            // With --replace the rewritten text is shown instead; its hits
+            // no longer line up with `spans`, so it isn't highlighted.
+            let lines: Vec<String> = match &config.replace {
+                Some(replacement) => {
+                    let replaced = config.pattern.replace_all(found.line, replacement);
+                    split_lines(&replaced).map(|(_, line)| line.to_string()).collect()
+                }
+                None => split_lines(found.line)
+                    .map(|(offset, line)| {
+                        let end = offset + line.len();
+                        let spans: Vec<Range<usize>> = found
+                            .spans
+                            .iter()
+                            .map(|span| span.start.clamp(offset, end) - offset..span.end.clamp(offset, end) - offset)
+                            .filter(|span| !span.is_empty())
+                            .collect();
+
+                        highlight(config, line, &spans)
+                    })
+                    .collect(),
+            };
+
+            // --fuzzy adds the edit distance as `~K` after the column.
+            let distance = found.distance.map_or(String::new(), |distance| format!("~{}:", distance));
+
+            // A --multiline match is numbered with the range it covers on
+            // its first line and line by line after that.
+            let range = if found.end_line_number > found.line_number {
+                format!("{}-{}", found.line_number, found.end_line_number)
+            } else {
+                found.line_number.to_string()
+            };
+
+            for (index, text) in lines.iter().enumerate() {
+                if let Some(label) = label {
+                    write!(out, "{}:", paint(config, FILE_NAME_COLOR, label))?;
+                }
+
+                if index == 0 {
+                    writeln!(out, "{}:{}:{}{}", paint(config, LINE_NUMBER_COLOR, &range), found.column(), distance, text)?;
+                } else {
+                    let number = (found.line_number + index).to_string();
+                    writeln!(out, "{}:{}", paint(config, LINE_NUMBER_COLOR, &number), text)?;
+                }
+            }
+
+            Ok(())
+        }
+        OutputFormat::Json => {
+            let submatches: Vec<_> = found
+                .spans
+                .iter()
+                .zip(&found.pattern_ids)
+                .map(|(span, &pattern)| {
+                    json!({
+                        "text": &found.line[span.clone()],
+                        "start": span.start,
+                        "end": span.end,
+                        "pattern": &config.patterns[pattern],
+                    })
+                })
+                .collect();
+
+            let mut record = json!({
+                "type": "match",
+                "file": label,
+                "line_number": found.line_number,
+                "column": found.column(),
+                "byte_offset": found.byte_offset,
+                "text": found.line,
+                "submatches": submatches,
+            });
+
+            if config.multiline {
+                record["end_line_number"] = json!(found.end_line_number);
+            }
+
+            if let Some(distance) = found.distance {
+                record["distance"] = json!(distance);
+            }
+
+            if let Some(replacement) = &config.replace {
+                record["replacement"] = json!(config.pattern.replace_all(found.line, replacement));
+            }
+
+            writeln!(out, "{}", record)
+        }
+    }
+}
+
+/// A [`Sink`] writing what it gets in `config.output` format.
+pub(crate) struct Printer<'a, W> {
+    pub out: &'a mut W,
+    pub config: &'a Config,
+    pub label: Option<&'a str>,
+}
+
+impl<W: Write> Sink for Printer<'_, W> {
+    fn matched(&mut self, found: &Match) -> io::Result<bool> {
+        write_match(self.out, self.config, self.label, found)?;
+        Ok(true)
+    }
+
+    fn context(&mut self, line_number: usize, line: &str) -> io::Result<()> {
+        write_context(self.out, self.config, self.label, line_number, line)
+    }
+
+    fn context_break(&mut self) -> io::Result<()> {
+        write_separator(self.out, self.config)
+    }
+}
+
+pub(crate) fn write_context<W: Write>(
+    out: &mut W,
+    config: &Config,
+    label: Option<&str>,
+    line_number: usize,
+    line: &str,
+) -> io::Result<()> {
+    match config.output {
+        OutputFormat::Text => {
+            if let Some(label) = label {
+                write!(out, "{}-", paint(config, FILE_NAME_COLOR, label))?;
+            }
+
+            writeln!(out, "{}-{}", paint(config, LINE_NUMBER_COLOR, &line_number.to_string()), line)
+        }
+        OutputFormat::Json => {
+            let record = json!({
+                "type": "context",
+                "file": label,
+                "line_number": line_number,
+                "text": line,
+            });
+
+            writeln!(out, "{}", record)
+        }
+    }
+}
+
+/// Splits the text of a match into its lines, with the offset of each. An
+/// empty line is still one line.
+fn split_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut offset = 0;
+
+    text.split('\n').map(move |line| {
+        let start = offset;
+        offset += line.len() + 1;
+
+        (start, line.strip_suffix('\r').unwrap_or(line))
+    })
+}
+
+/// Writes the `--` between context groups. JSON records carry their own
+/// line numbers, so there is nothing to separate there.
+pub(crate) fn write_separator<W: Write>(out: &mut W, config: &Config) -> io::Result<()> {
+    match config.output {
+        OutputFormat::Text => writeln!(out, "{}", paint(config, SEPARATOR_COLOR, "--")),
+        OutputFormat::Json => Ok(()),
+    }
+}
+
+/// Writes a `--dry-run` diff, which already carries its own file names.
+pub(crate) fn write_diff<W: Write>(out: &mut W, config: &Config, label: &str, diff: &str) -> io::Result<()> {
+    match config.output {
+        OutputFormat::Text => write!(out, "{}", diff),
+        OutputFormat::Json => writeln!(out, "{}", json!({ "type": "diff", "file": label, "diff": diff })),
+    }
+}
+
+/// Reports a selected line in binary input without printing it.
+pub(crate) fn write_binary_match<W: Write>(out: &mut W, config: &Config, name: &str) -> io::Result<()> {
+    match config.output {
+        OutputFormat::Text => writeln!(out, "Binary file {} matches", paint(config, FILE_NAME_COLOR, name)),
+        OutputFormat::Json => writeln!(out, "{}", json!({ "type": "binary", "file": name })),
+    }
+}
+
+/// Writes the number of selected lines for `-c`.
+pub(crate) fn write_count<W: Write>(
+    out: &mut W,
+    config: &Config,
+    label: Option<&str>,
+    count: usize,
+) -> io::Result<()> {
+    match (config.output, label) {
+        (OutputFormat::Text, Some(label)) => writeln!(out, "{}:{}", paint(config, FILE_NAME_COLOR, label), count),
+        (OutputFormat::Text, None) => writeln!(out, "{}", count),
+        (OutputFormat::Json, _) => writeln!(out, "{}", json!({ "type": "count", "file": label, "count": count })),
+    }
+}
+
+/// Writes the name of a file with a selected line for `-l`.
+pub(crate) fn write_file_name<W: Write>(out: &mut W, config: &Config, label: Option<&str>) -> io::Result<()> {
+    let name = label.unwrap_or("(standard input)");
+
+    match config.output {
+        OutputFormat::Text => writeln!(out, "{}", paint(config, FILE_NAME_COLOR, name)),
+        OutputFormat::Json => writeln!(out, "{}", json!({ "type": "file", "file": name })),
+    }
+}
+
+/// Writes the final JSON summary record; text output has none.
+pub(crate) fn write_summary<W: Write>(
+    out: &mut W,
+    config: &Config,
+    stats: &Stats,
+    elapsed: Duration,
+) -> io::Result<()> {
+    match config.output {
+        OutputFormat::Text => Ok(()),
+        OutputFormat::Json => {
+            let record = json!({
+                "type": "summary",
+                "files_searched": stats.files_searched,
+                "files_with_matches": stats.files_with_matches,
+                "matched_lines": stats.matched_lines,
+                "matches": stats.matches,
+                "elapsed_ms": elapsed.as_secs_f64() * 1000.0,
+            });
+
+            writeln!(out, "{}", record)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::config;
+
+    #[test]
+    fn highlights_every_span() {
+        let config = config(&["minigrep", "--color=always", "-i", "rust", "f"]);
+        let found = Match { line_number: 7, end_line_number: 7, byte_offset: 0, line: "Rust & RUST", spans: vec![0..4, 7..11], pattern_ids: vec![0, 0], distance: None };
+
+        let mut out = Vec::new();
+        write_match(&mut out, &config, Some("lib.rs"), &found).unwrap();
+
+        assert_eq!(
+            "\x1b[35mlib.rs\x1b[0m:\x1b[32m7\x1b[0m:1:\x1b[1;31mRust\x1b[0m & \x1b[1;31mRUST\x1b[0m\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn no_escape_codes_unless_asked() {
+        for choice in ["never", "auto"] {
+            let config = config(&["minigrep", &format!("--color={}", choice), "a", "f"]);
+            let found = Match { line_number: 1, end_line_number: 1, byte_offset: 0, line: "a", spans: vec![Range { start: 0, end: 1 }], pattern_ids: vec![0], distance: None };
+
+            let mut out = Vec::new();
+            write_match(&mut out, &config, None, &found).unwrap();
+
+            assert_eq!("1:1:a\n", String::from_utf8(out).unwrap());
+        }
+    }
+}
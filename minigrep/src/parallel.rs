@@ -0,0 +1,171 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use crate::{search_file, Config, Stats};
+
+/// How much output a worker collects before passing it on.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// What workers tell the thread that writes the output.
+enum Message {
+    /// More output for the file at this index.
+    Output(usize, Vec<u8>),
+    /// The file at this index is done.
+    Done(usize, Result<Stats, String>),
+}
+
+/// Output of a file that can't be written yet because an earlier one
+/// isn't done.
+#[derive(Default)]
+struct Pending {
+    output: Vec<u8>,
+    result: Option<Result<Stats, String>>,
+}
+
+/// Where a worker writes a file's results: sent on in chunks of about
+/// [`CHUNK_SIZE`], tagged with the file's index.
+struct Chunks<'a> {
+    index: usize,
+    buffer: Vec<u8>,
+    sender: &'a mpsc::Sender<Message>,
+}
+
+impl Chunks<'_> {
+    fn send(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = std::mem::take(&mut self.buffer);
+        // The receiver only goes away when writing to `out` failed.
+        self.sender
+            .send(Message::Output(self.index, chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "output was closed"))
+    }
+}
+
+impl Write for Chunks<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.send()?;
+        }
+
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+/// Searches `files` on a pool of `config.threads` workers.
+///
+/// Each worker takes the next unclaimed file and sends its results on in
+/// chunks. Output is written to `out` strictly in the order of `files`, so
+/// it is grouped per file and doesn't depend on which worker finished
+/// first. The first file that isn't done yet is streamed straight to `out`;
+/// only the output of files after it is held back until its turn. Returns
+/// the totals over every file that could be searched.
+pub fn search_files<W: Write>(config: &Config, files: &[PathBuf], out: &mut W) -> io::Result<Stats> {
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let workers = config.threads.min(files.len()).max(1);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let next = &next;
+
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = files.get(index) else { break };
+
+                let label = path.display().to_string();
+                let mut chunks = Chunks { index, buffer: Vec::new(), sender: &sender };
+                let result = search_file(config, path, Some(&label), &mut chunks).map_err(|e| e.to_string());
+
+                if chunks.send().is_err() || sender.send(Message::Done(index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+
+        drop(sender);
+
+        let mut pending: BTreeMap<usize, Pending> = BTreeMap::new();
+        let mut next_to_write = 0;
+        let mut stats = Stats::default();
+
+        for message in receiver {
+            match message {
+                Message::Output(index, chunk) if index == next_to_write => out.write_all(&chunk)?,
+                Message::Output(index, chunk) => pending.entry(index).or_default().output.extend(chunk),
+                Message::Done(index, result) => pending.entry(index).or_default().result = Some(result),
+            }
+
+            // Write out what the files from the head of the queue have so
+            // far, moving on past the ones that are done.
+            while let Some(file) = pending.get_mut(&next_to_write) {
+                out.write_all(&std::mem::take(&mut file.output))?;
+
+                let Some(result) = file.result.take() else { break };
+                pending.remove(&next_to_write);
+
+                match result {
+                    Ok(file_stats) => stats.add(&file_stats),
+                    Err(e) => eprintln!("Skipping {}: {}", files[next_to_write].display(), e),
+                }
+
+                next_to_write += 1;
+            }
+        }
+
+        Ok(stats)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::config;
+    use std::fs;
+
+    #[test]
+    fn output_follows_input_order() {
+        let root = tempfile::tempdir().unwrap();
+
+        // Earlier files are larger so they tend to finish last.
+        let files: Vec<PathBuf> = (0..16)
+            .map(|index| {
+                let path = root.path().join(format!("{:02}.txt", index));
+                fs::write(&path, format!("match {}\n", index).repeat((16 - index) * 500)).unwrap();
+                path
+            })
+            .collect();
+
+        let config = config(&["minigrep", "-j", "4", "match", "unused"]);
+
+        let mut out = Vec::new();
+        let stats = search_files(&config, &files, &mut out).unwrap();
+
+        assert_eq!(16, stats.files_with_matches);
+
+        let mut expected = String::new();
+        for (index, path) in files.iter().enumerate() {
+            for line_number in 1..=(16 - index) * 500 {
+                expected.push_str(&format!("{}:{}:1:match {}\n", path.display(), line_number, index));
+            }
+        }
+
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+}
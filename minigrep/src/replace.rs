@@ -0,0 +1,260 @@
+use std::{
+    error::Error,
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use tempfile::NamedTempFile;
+
+use crate::{decompress, encoding, output, trim_line_ending, Config, Match, Matcher, Stats};
+
+/// Lines of unchanged context shown around each change in a diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// Applies `--replace` to a whole file for `--write` or `--dry-run`.
+///
+/// With `--write`, the rewritten lines are printed like a normal search and
+/// the new contents are written to a temporary file next to `path`, which
+/// is then renamed over it, so readers never see a half-written file. With
+/// `--dry-run` nothing is written and a unified diff is printed instead.
+///
+/// The file is read like any search reads it, decompressed and transcoded
+/// to UTF-8 as needed. Writing such a file back would change more than the
+/// replaced text, so `--write` refuses to unless it was plain UTF-8.
+pub(crate) fn rewrite_file<W: Write>(
+    config: &Config,
+    path: &Path,
+    label: Option<&str>,
+    out: &mut W,
+) -> Result<Stats, Box<dyn Error>> {
+    if path == Path::new("-") {
+        return Err("standard input can't be rewritten".into());
+    }
+
+    let bytes = fs::read(path)?;
+    let reader = decompress::decompress(Box::new(bytes.as_slice()), Some(path), config.search_zip)?;
+    let (mut reader, binary) = encoding::decode(reader, config.encoding)?;
+    if binary {
+        return Err("binary file".into());
+    }
+
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+
+    let replacement = config.replace.as_deref().unwrap_or_default();
+    let mut stats = Stats { files_searched: 1, ..Stats::default() };
+    let mut old_lines = Vec::new();
+    let mut new_lines = Vec::new();
+    let mut rewritten = String::with_capacity(contents.len());
+    let mut found = Vec::new();
+    let mut byte_offset = 0;
+
+    for (index, raw) in contents.split_inclusive('\n').enumerate() {
+        let line = trim_line_ending(raw);
+        let ending = &raw[line.len()..];
+        let (spans, pattern_ids): (Vec<_>, Vec<_>) = config.pattern.find_all(line).into_iter().unzip();
+
+        let new_line = if spans.is_empty() {
+            line.to_string()
+        } else {
+            stats.matched_lines += 1;
+            stats.matches += spans.len();
+
+            if config.write {
+                let distance = config.pattern.distance(line);
+                found.push(Match { line_number: index + 1, end_line_number: index + 1, byte_offset, line, spans, pattern_ids, distance });
+            }
+
+            config.pattern.replace_all(line, replacement).into_owned()
+        };
+
+        rewritten.push_str(&new_line);
+        rewritten.push_str(ending);
+        old_lines.push(line);
+        new_lines.push(new_line);
+        byte_offset += raw.len();
+    }
+
+    stats.files_with_matches = usize::from(stats.matched_lines > 0);
+
+    if rewritten == contents {
+        return Ok(stats);
+    }
+
+    if config.write && contents.as_bytes() != bytes {
+        return Err("can't write back compressed or non-UTF-8 input (--dry-run shows the changes)".into());
+    }
+
+    for found in &found {
+        output::write_match(out, config, label, found)?;
+    }
+
+    if config.dry_run {
+        let name = path.display().to_string();
+        output::write_diff(out, config, &name, &unified_diff(&name, &old_lines, &new_lines))?;
+    } else {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let mut temp = NamedTempFile::new_in(dir)?;
+        temp.write_all(rewritten.as_bytes())?;
+        temp.as_file().set_permissions(fs::metadata(path)?.permissions())?;
+        temp.persist(path)?;
+    }
+
+    Ok(stats)
+}
+
+/// Builds a unified diff between `old_lines` and `new_lines`, where
+/// `new_lines[i]` replaces `old_lines[i]` and may itself span several lines
+/// when the replacement contains a newline.
+pub(crate) fn unified_diff(name: &str, old_lines: &[&str], new_lines: &[String]) -> String {
+    let changed: Vec<usize> = (0..old_lines.len()).filter(|&i| old_lines[i] != new_lines[i]).collect();
+
+    // Group changes whose context overlaps into the same hunk.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &index in &changed {
+        let start = index.saturating_sub(DIFF_CONTEXT);
+        let end = (index + DIFF_CONTEXT + 1).min(old_lines.len());
+
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => hunk.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let line_count = |index: usize| new_lines[index].split('\n').count();
+
+    // git-style a/ and b/ prefixes only make sense on relative paths.
+    let mut diff = if Path::new(name).is_absolute() {
+        format!("--- {}\n+++ {}\n", name, name)
+    } else {
+        format!("--- a/{}\n+++ b/{}\n", name, name)
+    };
+    let mut shift = 0;
+    let mut counted = 0;
+
+    for (start, end) in hunks {
+        // Lines added or removed by earlier changes move this hunk's start
+        // in the new file.
+        for index in counted..start {
+            shift += line_count(index) as isize - 1;
+        }
+        counted = start;
+
+        let new_count: usize = (start..end).map(line_count).sum();
+        let new_start = (start as isize + shift + 1) as usize;
+        diff.push_str(&format!("@@ -{},{} +{},{} @@\n", start + 1, end - start, new_start, new_count));
+
+        for index in start..end {
+            if old_lines[index] == new_lines[index] {
+                diff.push_str(&format!(" {}\n", old_lines[index]));
+            } else {
+                diff.push_str(&format!("-{}\n", old_lines[index]));
+                for line in new_lines[index].split('\n') {
+                    diff.push_str(&format!("+{}\n", line));
+                }
+            }
+        }
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::config;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    fn temp_file(contents: impl AsRef<[u8]>) -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn diff_hunks_and_line_numbers() {
+        let old_lines = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l"];
+        let mut new_lines: Vec<String> = old_lines.iter().map(|line| line.to_string()).collect();
+        new_lines[1] = "B1\nB2".to_string();
+        new_lines[10] = "K".to_string();
+
+        assert_eq!(
+            "\
+--- a/f.txt
++++ b/f.txt
+@@ -1,5 +1,6 @@
+ a
+-b
++B1
++B2
+ c
+ d
+ e
+@@ -8,5 +9,5 @@
+ h
+ i
+ j
+-k
++K
+ l
+",
+            unified_diff("f.txt", &old_lines, &new_lines)
+        );
+    }
+
+    #[test]
+    fn dry_run_leaves_the_file_alone() {
+        let (_dir, path) = temp_file("keep\nold value\n");
+        let config = config(&["minigrep", "--dry-run", "-r", "new", "old", "f"]);
+
+        let mut out = Vec::new();
+        rewrite_file(&config, &path, None, &mut out).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+
+        assert_eq!("keep\nold value\n", contents);
+        assert!(String::from_utf8(out).unwrap().contains("-old value\n+new value\n"));
+    }
+
+    #[test]
+    fn write_replaces_in_place() {
+        let (_dir, path) = temp_file("Old\r\nkeep\nOLD and old");
+        let config = config(&["minigrep", "-i", "--write", "--replace", "new", "old", "f"]);
+
+        let mut out = Vec::new();
+        let stats = rewrite_file(&config, &path, Some("f"), &mut out).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+
+        assert_eq!("new\r\nkeep\nnew and new", contents);
+        assert_eq!(2, stats.matched_lines);
+        assert_eq!("f:1:1:new\nf:3:1:new and new\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn decoded_files_are_only_previewed() {
+        let (_dir, path) = temp_file(b"caf\xe9 cr\xe8me\n");
+
+        let mut out = Vec::new();
+        let dry_run = config(&["minigrep", "--dry-run", "-r", "tea", "café", "f"]);
+        rewrite_file(&dry_run, &path, None, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("-café crème\n+tea crème\n"));
+
+        let mut out = Vec::new();
+        let write = config(&["minigrep", "--write", "-r", "tea", "café", "f"]);
+        assert!(rewrite_file(&write, &path, None, &mut out).is_err());
+        assert!(out.is_empty());
+        assert_eq!(b"caf\xe9 cr\xe8me\n".to_vec(), fs::read(&path).unwrap());
+
+        let compressed = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/log.txt.gz");
+        let before = fs::read(&compressed).unwrap();
+        let write = config(&["minigrep", "--write", "-r", "WARN", "ERROR", "f"]);
+        assert!(rewrite_file(&write, &compressed, None, &mut out).is_err());
+        assert_eq!(before, fs::read(&compressed).unwrap());
+    }
+}
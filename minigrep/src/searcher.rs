@@ -0,0 +1,364 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead},
+    ops::Range,
+};
+
+use regex::Regex;
+
+use crate::{lines_with_offsets, trim_line_ending, Config, Match, Stats};
+
+/// Finds hits in a line. [`LiteralMatcher`](crate::LiteralMatcher),
+/// [`CaseInsensitiveMatcher`](crate::CaseInsensitiveMatcher),
+/// [`BoundedMatcher`](crate::BoundedMatcher),
+/// [`FuzzyMatcher`](crate::FuzzyMatcher) and
+/// [`RegexMatcher`](crate::RegexMatcher) each do one kind of search;
+/// [`Pattern`](crate::Pattern) picks the one the command line asks for and
+/// adds `--field` queries on top.
+pub trait Matcher {
+    /// Every non-overlapping hit in `line`, in order, with the index of
+    /// the pattern that produced it.
+    fn find_all(&self, line: &str) -> Vec<(Range<usize>, usize)>;
+
+    fn is_match(&self, line: &str) -> bool {
+        !self.find_all(line).is_empty()
+    }
+
+    /// Edit distance of the closest hit, for matchers that allow typos.
+    fn distance(&self, _line: &str) -> Option<usize> {
+        None
+    }
+}
+
+/// A single regular expression, as is.
+impl Matcher for Regex {
+    fn find_all(&self, line: &str) -> Vec<(Range<usize>, usize)> {
+        self.find_iter(line).map(|found| (found.range(), 0)).collect()
+    }
+}
+
+/// Receives what a [`Searcher`] finds, in input order. Any
+/// `FnMut(&Match) -> io::Result<bool>` closure is a sink that ignores
+/// context lines.
+pub trait Sink {
+    /// Called for each selected line (or lines, with `multiline`).
+    /// Returning `false` stops the search.
+    fn matched(&mut self, found: &Match) -> io::Result<bool>;
+
+    /// Called for each line of context around a match.
+    fn context(&mut self, _line_number: usize, _line: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called between groups of lines that aren't adjacent, where grep
+    /// prints `--`. Only happens when there is context.
+    fn context_break(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&Match) -> io::Result<bool>> Sink for F {
+    fn matched(&mut self, found: &Match) -> io::Result<bool> {
+        self(found)
+    }
+}
+
+/// Decides which lines of an input are selected and hands them, with
+/// their context, to a [`Sink`]. What counts as a hit is up to the
+/// [`Matcher`]; how the results are shown is up to the sink.
+#[derive(Clone, Debug, Default)]
+pub struct Searcher {
+    /// Select the lines without a hit instead (`-v`).
+    pub invert_match: bool,
+    /// Stop after this many selected lines, or matches with `multiline`.
+    pub max_count: Option<usize>,
+    pub before_context: usize,
+    pub after_context: usize,
+    /// Run the matcher over the whole input at once, so hits can span
+    /// lines. The input is then held in memory.
+    pub multiline: bool,
+}
+
+impl From<&Config> for Searcher {
+    fn from(config: &Config) -> Searcher {
+        Searcher {
+            invert_match: config.invert_match,
+            max_count: config.max_count,
+            before_context: config.before_context,
+            after_context: config.after_context,
+            multiline: config.multiline,
+        }
+    }
+}
+
+impl Searcher {
+    pub fn search_str<M: Matcher + ?Sized, S: Sink>(&self, matcher: &M, contents: &str, sink: &mut S) -> io::Result<Stats> {
+        self.search_reader(matcher, contents.as_bytes(), sink)
+    }
+
+    /// Searches `reader` one line at a time. Only the lines needed for
+    /// `before_context` are held in memory, so this works on inputs far
+    /// larger than RAM.
+    pub fn search_reader<M: Matcher + ?Sized, R: BufRead, S: Sink>(&self, matcher: &M, mut reader: R, sink: &mut S) -> io::Result<Stats> {
+        if self.multiline {
+            let mut contents = String::new();
+            reader.read_to_string(&mut contents)?;
+
+            return self.search_whole(matcher, &contents, sink);
+        }
+
+        let show_separators = self.before_context > 0 || self.after_context > 0;
+        let limit_reached = |stats: &Stats| self.max_count.is_some_and(|max| stats.matched_lines >= max);
+
+        let mut before: VecDeque<(usize, String)> = VecDeque::with_capacity(self.before_context);
+        let mut after_remaining = 0;
+        let mut last_printed: Option<usize> = None;
+        let mut stats = Stats { files_searched: 1, ..Stats::default() };
+
+        let mut buffer = String::new();
+        let mut line_number = 0;
+        let mut next_offset = 0;
+
+        // With a limit, stop reading once it is hit and its trailing
+        // context has been passed on.
+        while !(limit_reached(&stats) && after_remaining == 0) {
+            buffer.clear();
+            let read = reader.read_line(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+
+            line_number += 1;
+            let byte_offset = next_offset;
+            next_offset += read;
+            let line = trim_line_ending(&buffer);
+
+            let (spans, pattern_ids): (Vec<_>, Vec<_>) = matcher.find_all(line).into_iter().unzip();
+            let selected = spans.is_empty() == self.invert_match && !limit_reached(&stats);
+
+            if selected {
+                stats.matched_lines += 1;
+                stats.matches += spans.len();
+
+                let first = before.front().map_or(line_number, |(number, _)| *number);
+                if show_separators && last_printed.is_some_and(|last| first > last + 1) {
+                    sink.context_break()?;
+                }
+
+                for (number, context) in before.drain(..) {
+                    sink.context(number, &context)?;
+                }
+
+                let distance = matcher.distance(line);
+                let found = Match { line_number, end_line_number: line_number, byte_offset, line, spans, pattern_ids, distance };
+                if !sink.matched(&found)? {
+                    break;
+                }
+
+                last_printed = Some(line_number);
+                after_remaining = self.after_context;
+            } else if after_remaining > 0 {
+                sink.context(line_number, line)?;
+
+                last_printed = Some(line_number);
+                after_remaining -= 1;
+            } else if self.before_context > 0 {
+                if before.len() == self.before_context {
+                    before.pop_front();
+                }
+                before.push_back((line_number, line.to_string()));
+            }
+        }
+
+        stats.files_with_matches = usize::from(stats.matched_lines > 0);
+
+        Ok(stats)
+    }
+
+    /// The `multiline` search, over input already in memory.
+    fn search_whole<M: Matcher + ?Sized, S: Sink>(&self, matcher: &M, contents: &str, sink: &mut S) -> io::Result<Stats> {
+        let mut matches = find_multiline(matcher, contents);
+        if let Some(max) = self.max_count {
+            matches.truncate(max);
+        }
+
+        let lines: Vec<&str> = lines_with_offsets(contents).map(|(_, line)| line).collect();
+        let show_separators = self.before_context > 0 || self.after_context > 0;
+        let mut stats = Stats { files_searched: 1, ..Stats::default() };
+        // 1-based; 0 before anything is passed on.
+        let mut last_printed = 0;
+        let mut after_until = 0;
+
+        for found in &matches {
+            let trailing = after_until.min(found.line_number - 1);
+            for number in last_printed + 1..=trailing {
+                sink.context(number, lines[number - 1])?;
+            }
+            last_printed = last_printed.max(trailing);
+
+            let leading = found.line_number.saturating_sub(self.before_context).max(last_printed + 1);
+            if show_separators && last_printed > 0 && leading > last_printed + 1 {
+                sink.context_break()?;
+            }
+            for number in leading..found.line_number {
+                sink.context(number, lines[number - 1])?;
+            }
+
+            stats.matched_lines += found.end_line_number - found.line_number + 1;
+            stats.matches += found.spans.len();
+            stats.files_with_matches = 1;
+
+            if !sink.matched(found)? {
+                return Ok(stats);
+            }
+
+            last_printed = found.end_line_number;
+            after_until = found.end_line_number + self.after_context;
+        }
+
+        for number in last_printed + 1..=after_until.min(lines.len()) {
+            sink.context(number, lines[number - 1])?;
+        }
+
+        Ok(stats)
+    }
+}
+
+/// Runs `matcher` over all of `contents` at once, so hits can span line
+/// breaks. Each [`Match`] covers the whole lines its hits touch; hits on
+/// the same lines share one.
+pub(crate) fn find_multiline<'a, M: Matcher + ?Sized>(matcher: &M, contents: &'a str) -> Vec<Match<'a>> {
+    let lines: Vec<(usize, &str)> = lines_with_offsets(contents).collect();
+    if lines.is_empty() {
+        return Vec::new();
+    }
+
+    // 0-based index of the line holding the byte at `offset`.
+    let line_at = |offset: usize| lines.partition_point(|&(start, _)| start <= offset) - 1;
+
+    // Spans stay relative to `contents` until each match's lines are known.
+    let mut matches: Vec<Match> = Vec::new();
+
+    for (span, pattern_id) in matcher.find_all(contents) {
+        let first = line_at(span.start) + 1;
+        // A hit ending in a line break doesn't reach into the next line.
+        let last = line_at(span.end.saturating_sub(1).max(span.start)) + 1;
+
+        match matches.last_mut() {
+            Some(found) if first <= found.end_line_number => {
+                found.end_line_number = last.max(found.end_line_number);
+                found.spans.push(span);
+                found.pattern_ids.push(pattern_id);
+            }
+            _ => matches.push(Match {
+                line_number: first,
+                end_line_number: last,
+                byte_offset: lines[first - 1].0,
+                line: "",
+                spans: vec![span],
+                pattern_ids: vec![pattern_id],
+                distance: None,
+            }),
+        }
+    }
+
+    for found in &mut matches {
+        let (start, last_line) = lines[found.end_line_number - 1];
+        let end = start + last_line.len();
+
+        found.line = &contents[found.byte_offset..end];
+        for span in &mut found.spans {
+            *span = span.start.min(end) - found.byte_offset..span.end.min(end) - found.byte_offset;
+        }
+    }
+
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Boundary, Pattern};
+
+    const LOG: &str = "\
+boot
+error: disk
+ok
+ok
+Error: net
+ok";
+
+    /// Collects what the searcher reports as `line:text` and `line-text`.
+    #[derive(Default)]
+    struct Lines(Vec<String>);
+
+    impl Sink for Lines {
+        fn matched(&mut self, found: &Match) -> io::Result<bool> {
+            self.0.push(format!("{}:{}", found.line_number, found.line));
+            Ok(true)
+        }
+
+        fn context(&mut self, line_number: usize, line: &str) -> io::Result<()> {
+            self.0.push(format!("{}-{}", line_number, line));
+            Ok(())
+        }
+
+        fn context_break(&mut self) -> io::Result<()> {
+            self.0.push("--".to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn closures_collect_matches() {
+        let matcher = Pattern::literal(&["error".to_string()], false, Boundary::Substring).unwrap();
+        let mut found = Vec::new();
+
+        let stats = Searcher::default()
+            .search_str(&matcher, LOG, &mut |hit: &Match| {
+                found.push((hit.line_number, hit.matched_text().to_string()));
+                Ok(true)
+            })
+            .unwrap();
+
+        assert_eq!(vec![(2, "error".to_string()), (5, "Error".to_string())], found);
+        assert_eq!((2, 2, 1), (stats.matched_lines, stats.matches, stats.files_with_matches));
+    }
+
+    #[test]
+    fn sinks_get_context_and_can_stop() {
+        let matcher = Regex::new("^[eE]rror").unwrap();
+        let searcher = Searcher { before_context: 1, ..Searcher::default() };
+
+        let mut lines = Lines::default();
+        searcher.search_str(&matcher, LOG, &mut lines).unwrap();
+        assert_eq!(vec!["1-boot", "2:error: disk", "--", "4-ok", "5:Error: net"], lines.0);
+
+        let mut calls = 0;
+        searcher
+            .search_str(&matcher, LOG, &mut |_: &Match| {
+                calls += 1;
+                Ok(false)
+            })
+            .unwrap();
+        assert_eq!(1, calls);
+    }
+
+    #[test]
+    fn custom_matchers() {
+        /// Lines of exactly `n` bytes.
+        struct Length(usize);
+
+        impl Matcher for Length {
+            fn find_all(&self, line: &str) -> Vec<(Range<usize>, usize)> {
+                if line.len() == self.0 { vec![(0..line.len(), 0)] } else { Vec::new() }
+            }
+        }
+
+        let searcher = Searcher { invert_match: true, max_count: Some(2), ..Searcher::default() };
+        let mut lines = Lines::default();
+        searcher.search_str(&Length(2), LOG, &mut lines).unwrap();
+
+        assert_eq!(vec!["1:boot", "2:error: disk"], lines.0);
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use toml::{Table, Value};
+
+use crate::{
+    config::{CaseMode, ConfigError},
+    walk, ColorChoice, OutputFormat,
+};
+
+/// Name of the configuration file looked for in the user's config directory
+/// and in the current directory or its nearest ancestor that has one.
+pub(crate) const FILE_NAME: &str = "minigrep.toml";
+
+/// Where an effective setting came from, as `--print-config` shows it.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Source {
+    Default,
+    File(PathBuf),
+    Environment(&'static str),
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Environment(name) => write!(f, "{} environment variable", name),
+            Source::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// Defaults read from `minigrep.toml` files. For single values the last
+/// file to set one wins; `ignore` globs from every file are combined.
+#[derive(Debug, Default)]
+pub(crate) struct FileSettings {
+    /// The files that exist and were read, in order.
+    pub files: Vec<PathBuf>,
+    pub case: Option<(CaseMode, Source)>,
+    pub color: Option<(ColorChoice, Source)>,
+    pub output: Option<(OutputFormat, Source)>,
+    /// Globs of files to skip in directories, as if passed as `--glob '!GLOB'`.
+    pub ignore: Vec<(String, Source)>,
+}
+
+/// The user-level file followed by the project-level one, so the project's
+/// settings win. Neither has to exist.
+pub(crate) fn default_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(dir) = config_dir {
+        files.push(dir.join("minigrep").join(FILE_NAME));
+    }
+
+    if let Ok(current) = env::current_dir() {
+        let project = current.ancestors().map(|dir| dir.join(FILE_NAME)).find(|path| path.is_file());
+        files.extend(project.filter(|project| !files.contains(project)));
+    }
+
+    files
+}
+
+/// Reads every file in `files` that exists, in order.
+pub(crate) fn load(files: &[PathBuf]) -> Result<FileSettings, ConfigError> {
+    let mut settings = FileSettings::default();
+
+    for path in files {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(invalid(path, e)),
+        };
+        let table: Table = contents.parse().map_err(|e| invalid(path, e))?;
+        settings.files.push(path.clone());
+
+        for (key, value) in &table {
+            let source = Source::File(path.clone());
+
+            match key.as_str() {
+                "case" => {
+                    let choices = [("sensitive", CaseMode::Sensitive), ("insensitive", CaseMode::Insensitive), ("smart", CaseMode::Smart)];
+                    settings.case = Some((choice(path, key, value, &choices)?, source));
+                }
+                "color" => {
+                    let choices = [("always", ColorChoice::Always), ("never", ColorChoice::Never), ("auto", ColorChoice::Auto)];
+                    settings.color = Some((choice(path, key, value, &choices)?, source));
+                }
+                "output" => {
+                    let choices = [("text", OutputFormat::Text), ("json", OutputFormat::Json)];
+                    settings.output = Some((choice(path, key, value, &choices)?, source));
+                }
+                "ignore" => {
+                    let globs = value.as_array().ok_or_else(|| invalid(path, "`ignore` must be a list of globs"))?;
+
+                    for glob in globs {
+                        let glob = glob.as_str().ok_or_else(|| invalid(path, "`ignore` must be a list of globs"))?;
+                        walk::validate_glob(&format!("!{}", glob)).map_err(|e| invalid(path, format!("glob {:?}: {}", glob, e)))?;
+                        settings.ignore.push((glob.to_string(), source.clone()));
+                    }
+                }
+                _ => return Err(invalid(path, format!("unknown setting `{}`", key))),
+            }
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Renders the effective settings as TOML, each with where it came from.
+pub(crate) fn describe(files: &[PathBuf], settings: &[(&str, Value, String)]) -> String {
+    let mut description = if files.is_empty() {
+        format!("# No {} found\n", FILE_NAME)
+    } else {
+        let files: Vec<String> = files.iter().map(|file| file.display().to_string()).collect();
+        format!("# Read {}\n", files.join(", "))
+    };
+
+    let lines: Vec<String> = settings.iter().map(|(key, value, _)| format!("{} = {}", key, value)).collect();
+    let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+
+    for (line, (_, _, source)) in lines.iter().zip(settings) {
+        description.push_str(&format!("{:width$}  # {}\n", line, source, width = width));
+    }
+
+    description.trim_end().to_string()
+}
+
+/// Looks `value` up among the allowed `choices` for `key`.
+fn choice<T: Copy>(path: &Path, key: &str, value: &Value, choices: &[(&str, T)]) -> Result<T, ConfigError> {
+    let found = value.as_str().and_then(|value| choices.iter().find(|(name, _)| *name == value));
+
+    match found {
+        Some((_, choice)) => Ok(*choice),
+        None => {
+            let names: Vec<&str> = choices.iter().map(|(name, _)| *name).collect();
+            Err(invalid(path, format!("`{}` must be one of {}, not {}", key, names.join(", "), value)))
+        }
+    }
+}
+
+fn invalid(path: &Path, message: impl fmt::Display) -> ConfigError {
+    ConfigError::ConfigFile(path.display().to_string(), message.to_string())
+}
@@ -0,0 +1,122 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+
+use crate::{index, Config};
+
+/// Collects the files below `root` that should be searched, sorted so
+/// output is stable between runs.
+///
+/// By default `.gitignore`, `.ignore` and git's global and `info/exclude`
+/// rules are honored and hidden files are skipped; `--no-ignore` and
+/// `--hidden` turn those off. `--glob` patterns are applied on top: a plain
+/// glob restricts the walk to matching files and a glob starting with `!`
+/// excludes them. Entries that can't be read are reported on stderr and
+/// skipped; symlinks are not followed. Indexes written by `minigrep index`
+/// are never searched. An invalid glob in `config.globs` is an error.
+pub fn files(root: &Path, config: &Config) -> io::Result<Vec<PathBuf>> {
+    let invalid = |e: ignore::Error| io::Error::new(io::ErrorKind::InvalidInput, e);
+
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in &config.globs {
+        overrides.add(glob).map_err(invalid)?;
+    }
+    let overrides = overrides.build().map_err(invalid)?;
+
+    let walker = WalkBuilder::new(root)
+        .standard_filters(!config.no_ignore)
+        .hidden(!config.hidden)
+        .require_git(false)
+        .overrides(overrides)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    let mut files = Vec::new();
+
+    for entry in walker {
+        match entry {
+            Ok(entry) => {
+                if entry.file_type().is_some_and(|file_type| file_type.is_file()) && entry.file_name() != index::FILE_NAME {
+                    files.push(entry.into_path());
+                }
+            }
+            Err(e) => eprintln!("Skipping {}", e),
+        }
+    }
+
+    Ok(files)
+}
+
+/// Checks that `glob` is something `--glob` can use.
+pub(crate) fn validate_glob(glob: &str) -> Result<(), ignore::Error> {
+    OverrideBuilder::new(".").add(glob).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::config;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn tree() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("b/nested")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::write(root.join("b/nested/deep.rs"), "deep").unwrap();
+        fs::write(root.join("b/file.txt"), "b").unwrap();
+        fs::write(root.join("a.rs"), "a").unwrap();
+        fs::write(root.join("Cargo.lock"), "lock").unwrap();
+        fs::write(root.join(".hidden"), "hidden").unwrap();
+        fs::write(root.join("target/debug/out.rs"), "generated").unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn walks_nested_directories_in_order() {
+        let dir = tree();
+        let root = dir.path();
+        let found = files(root, &config(&["minigrep", "q", "."])).unwrap();
+
+        assert_eq!(
+            vec![root.join("Cargo.lock"), root.join("a.rs"), root.join("b/file.txt"), root.join("b/nested/deep.rs")],
+            found
+        );
+    }
+
+    #[test]
+    fn globs_include_and_exclude() {
+        let dir = tree();
+        let root = dir.path();
+        let only_rust = files(root, &config(&["minigrep", "--glob", "*.rs", "q", "."])).unwrap();
+        let no_lock = files(root, &config(&["minigrep", "--glob=!*.lock", "q", "."])).unwrap();
+
+        assert_eq!(vec![root.join("a.rs"), root.join("b/nested/deep.rs")], only_rust);
+        assert_eq!(vec![root.join("a.rs"), root.join("b/file.txt"), root.join("b/nested/deep.rs")], no_lock);
+    }
+
+    #[test]
+    fn no_ignore_and_hidden() {
+        let dir = tree();
+        let root = dir.path();
+        let found = files(root, &config(&["minigrep", "--no-ignore", "--hidden", "q", "."])).unwrap();
+
+        assert!(found.contains(&root.join("target/debug/out.rs")));
+        assert!(found.contains(&root.join(".hidden")));
+        assert!(found.contains(&root.join(".gitignore")));
+    }
+
+    #[test]
+    fn invalid_globs_are_errors() {
+        let dir = tree();
+        let mut config = config(&["minigrep", "q", "."]);
+        config.globs.push("*.{rs".to_string());
+
+        assert_eq!(io::ErrorKind::InvalidInput, files(dir.path(), &config).unwrap_err().kind());
+    }
+}